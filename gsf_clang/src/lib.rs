@@ -35,6 +35,86 @@ pub struct {ident} {{
     )
 }
 
+fn write_enum_decl<W>(ty: &gsf::Ty, mut to: W) -> io::Result<()>
+where
+    W: Write,
+{
+    if ty.variants.is_empty() || ty.variants.iter().any(|v| v.discriminant.is_none()) {
+        return write_ty_decl(ty, to);
+    }
+
+    let variants: String = ty.variants
+        .iter()
+        .filter_map(|v| v.discriminant.map(|d| format!("    {} = {},\n", v.ident, d)))
+        .collect();
+    write!(
+        to,
+        "\
+#[repr(C)]
+pub enum {ident} {{
+{variants}}}",
+        ident = ty.ident,
+        variants = variants,
+    )
+}
+
+fn const_literal(val: &gsf::Value) -> Option<(&'static str, String)> {
+    let literal = match *val {
+        gsf::Value::Bool(x) => ("bool", x.to_string()),
+        gsf::Value::Int8(x) => ("i8", x.to_string()),
+        gsf::Value::Int16(x) => ("i16", x.to_string()),
+        gsf::Value::Int32(x) => ("i32", x.to_string()),
+        gsf::Value::Int64(x) => ("i64", x.to_string()),
+        gsf::Value::Uint8(x) => ("u8", x.to_string()),
+        gsf::Value::Uint16(x) => ("u16", x.to_string()),
+        gsf::Value::Uint32(x) => ("u32", x.to_string()),
+        gsf::Value::Uint64(x) => ("u64", x.to_string()),
+        gsf::Value::Float32(x) => ("f32", format!("{:?}", x)),
+        gsf::Value::Float64(x) => ("f64", format!("{:?}", x)),
+        gsf::Value::String(ref x) => ("&'static str", format!("{:?}", x)),
+        _ => return None,
+    };
+
+    Some(literal)
+}
+
+fn write_consts<W>(ty: &gsf::Ty, mut to: W) -> io::Result<()>
+where
+    W: Write,
+{
+    for constant in &ty.constants {
+        if let Some((rust_ty, literal)) = const_literal(&(constant.value)()) {
+            write!(
+                to,
+                "pub const {ty}_{ident}: {rust_ty} = {literal};\n",
+                ty = ty.ident,
+                ident = constant.ident,
+                rust_ty = rust_ty,
+                literal = literal,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_tys<W>(map: &gsf::TyMap, mut to: W) -> io::Result<()>
+where
+    W: Write,
+{
+    let mut tys: Vec<&gsf::Ty> = map.values().collect();
+    tys.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+    for ty in tys {
+        write_enum_decl(ty, &mut to)?;
+        write!(to, "\n")?;
+        write_consts(ty, &mut to)?;
+        write!(to, "\n")?;
+    }
+
+    Ok(())
+}
+
 struct FunMeta {
     args: String,
     pass_args: String,
@@ -121,6 +201,79 @@ where
 mod tests {
     use super::*;
 
+    #[derive(Clone)]
+    enum KeyCode {
+        Escape,
+        Enter,
+    }
+
+    struct Window;
+
+    #[test]
+    fn ty_decls() {
+        let map = gsf::Builder::default()
+            .with_enum(
+                gsf::EnumBuilder::<KeyCode>::new("KeyCode")
+                    .with_unit("Escape", KeyCode::Escape)
+                    .with_discriminant(27)
+                    .with_unit("Enter", KeyCode::Enter),
+            )
+            .with_ty(gsf::TyBuilder::<Window>::new("Window"))
+            .finish()
+            .unwrap();
+        let mut v: Vec<u8> = Vec::new();
+
+        write_tys(&map, &mut v).unwrap();
+
+        assert_eq!(
+            String::from_utf8(v).unwrap(),
+            "\
+#[repr(C)]
+pub enum KeyCode {
+    Escape = 27,
+    Enter = 28,
+}
+
+#[repr(C)]
+pub struct Window {
+    _inner: *mut (),
+}
+
+"
+        );
+    }
+
+    #[test]
+    fn consts() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Window>::new("Window")
+                    .with_constant("MAX_WIDTH", 1920u32)
+                    .with_constant("SCALE", 2.5f32)
+                    .with_constant("TITLE", String::from("gsf"))
+                    .with_constant("NONE", vec![1]),
+            )
+            .finish()
+            .unwrap();
+        let mut v: Vec<u8> = Vec::new();
+
+        write_tys(&map, &mut v).unwrap();
+
+        assert_eq!(
+            String::from_utf8(v).unwrap(),
+            "\
+#[repr(C)]
+pub struct Window {
+    _inner: *mut (),
+}
+pub const Window_MAX_WIDTH: u32 = 1920;
+pub const Window_SCALE: f32 = 2.5;
+pub const Window_TITLE: &'static str = \"gsf\";
+
+"
+        );
+    }
+
     #[test]
     fn conv_func() {
         let mut v: Vec<u8> = Vec::new();
@@ -131,6 +284,8 @@ mod tests {
                 exec: Arc::new(|_| gsf::Value::Nil),
                 ident: "foo".into(),
                 args: vec![ValueTy::Uint64, ValueTy::Uint64],
+                arg_names: vec![],
+                defaults: vec![],
                 ret: ValueTy::Void,
             },
         );
@@ -0,0 +1,254 @@
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use syn::{
+    Attribute, Data, DeriveInput, Fields, FnArg, Ident, ImplItem, ImplItemMethod, ItemImpl, Lit,
+    Meta, NestedMeta, ReturnType, Type,
+};
+
+#[proc_macro_derive(Gsf, attributes(gsf))]
+pub fn derive_gsf(input: TokenStream) -> TokenStream {
+    let input: DeriveInput = syn::parse(input).expect("Failed to parse derive input");
+
+    derive_fields(&input).into()
+}
+
+#[proc_macro_attribute]
+pub fn gsf_impl(_: TokenStream, input: TokenStream) -> TokenStream {
+    let mut item: ItemImpl = syn::parse(input).expect("#[gsf_impl] only supports impl blocks");
+
+    let items = impl_items(&mut item);
+
+    let expanded = quote! {
+        #item
+
+        #items
+    };
+
+    expanded.into()
+}
+
+#[derive(Default)]
+struct Options {
+    skip: bool,
+    readonly: bool,
+    rename: Option<String>,
+}
+
+impl Options {
+    fn parse(attrs: &[Attribute]) -> Self {
+        let mut options = Options::default();
+
+        for attr in attrs.iter().filter(|attr| is_gsf(attr)) {
+            let list = match attr.parse_meta() {
+                Ok(Meta::List(list)) => list,
+                _ => panic!("Expected #[gsf(..)]"),
+            };
+
+            for nested in list.nested {
+                match nested {
+                    NestedMeta::Meta(Meta::Word(ref word)) if word == "skip" => options.skip = true,
+                    NestedMeta::Meta(Meta::Word(ref word)) if word == "readonly" => {
+                        options.readonly = true
+                    }
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "rename" => {
+                        match nv.lit {
+                            Lit::Str(ref s) => options.rename = Some(s.value()),
+                            _ => panic!("Expected #[gsf(rename = \"..\")]"),
+                        }
+                    }
+                    _ => panic!("Unknown gsf attribute, expected skip, readonly or rename"),
+                }
+            }
+        }
+
+        options
+    }
+}
+
+fn is_gsf(attr: &Attribute) -> bool {
+    attr.path.segments.len() == 1 && attr.path.segments[0].ident == "gsf"
+}
+
+fn flavours() -> Vec<TokenStream2> {
+    vec![quote!(::gsf::Local), quote!(::gsf::ThreadSafe)]
+}
+
+fn derive_fields(input: &DeriveInput) -> TokenStream2 {
+    let ty = &input.ident;
+    if !input.generics.params.is_empty() {
+        panic!("#[derive(Gsf)] does not support generic types");
+    }
+
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields.named.iter().collect(),
+            Fields::Unit => vec![],
+            Fields::Unnamed(_) => panic!("#[derive(Gsf)] only supports structs with named fields"),
+        },
+        _ => panic!("#[derive(Gsf)] only supports structs"),
+    };
+
+    let properties: Vec<_> = fields
+        .into_iter()
+        .filter_map(|field| {
+            let options = Options::parse(&field.attrs);
+            if options.skip {
+                return None;
+            }
+
+            let field_ident = field.ident.as_ref().unwrap();
+            let field_ty = &field.ty;
+            let name = options.rename.unwrap_or_else(|| field_ident.to_string());
+            let setter = if options.readonly {
+                quote!()
+            } else {
+                quote! {
+                    .with_setter(|this: &mut #ty, value: #field_ty| this.#field_ident = value)
+                }
+            };
+
+            Some(quote! {
+                builder.add_property(
+                    ::gsf::PropertyBuilder::new(#name)
+                        .with_getter(|this: &#ty| this.#field_ident.clone())
+                        #setter
+                );
+            })
+        })
+        .collect();
+
+    let name = ty.to_string();
+    let impls = flavours().into_iter().map(|flavour| {
+        let properties = &properties;
+
+        quote! {
+            impl ::gsf::Fields<#flavour> for #ty {
+                fn ident() -> &'static str {
+                    #name
+                }
+
+                #[allow(unused_variables)]
+                fn add_fields(builder: &mut ::gsf::TyBuilder<Self, #flavour>) {
+                    #(#properties)*
+                }
+            }
+        }
+    });
+
+    quote!(#(#impls)*)
+}
+
+fn impl_items(item: &mut ItemImpl) -> TokenStream2 {
+    if item.trait_.is_some() || !item.generics.params.is_empty() {
+        panic!("#[gsf_impl] only supports inherent impls of non-generic types");
+    }
+
+    let self_ty = item.self_ty.clone();
+    let mut registrations = vec![];
+
+    for impl_item in &mut item.items {
+        if let ImplItem::Method(ref mut method) = *impl_item {
+            let options = Options::parse(&method.attrs);
+            method.attrs.retain(|attr| !is_gsf(attr));
+            if options.readonly {
+                panic!("#[gsf(readonly)] only applies to fields, not to fn {}", method.sig.ident);
+            }
+
+            if !options.skip {
+                registrations.push(register_method(&self_ty, method, options.rename));
+            }
+        }
+    }
+
+    let impls = flavours().into_iter().map(|flavour| {
+        let registrations = &registrations;
+
+        quote! {
+            impl ::gsf::Items<#flavour> for #self_ty {
+                #[allow(unused_variables)]
+                fn add_items(builder: &mut ::gsf::TyBuilder<Self, #flavour>) {
+                    #(#registrations)*
+                }
+            }
+        }
+    });
+
+    quote!(#(#impls)*)
+}
+
+fn register_method(
+    self_ty: &Type,
+    method: &ImplItemMethod,
+    rename: Option<String>,
+) -> TokenStream2 {
+    let sig = &method.sig;
+    let fn_ident = &sig.ident;
+    let name = rename.unwrap_or_else(|| fn_ident.to_string());
+    if !sig.decl.generics.params.is_empty() {
+        panic!("#[gsf_impl] cannot register generic fn {}, add #[gsf(skip)]", fn_ident);
+    }
+
+    let mut receiver = None;
+    let mut arg_tys = vec![];
+    for arg in &sig.decl.inputs {
+        match *arg {
+            FnArg::SelfRef(ref s) => receiver = Some(s.mutability.is_some()),
+            FnArg::SelfValue(_) => panic!(
+                "#[gsf_impl] cannot register {} taking self by value, add #[gsf(skip)]",
+                fn_ident
+            ),
+            FnArg::Captured(ref arg) => arg_tys.push(arg.ty.clone()),
+            _ => panic!("#[gsf_impl] cannot register {}, unsupported argument", fn_ident),
+        }
+    }
+
+    let args: Vec<_> = (0..arg_tys.len())
+        .map(|i| Ident::new(&format!("arg{}", i), Span::call_site()))
+        .collect();
+    let (args, arg_tys) = (&args, &arg_tys);
+    let pattern = quote!((#(#args,)*): (#(#arg_tys,)*));
+    let call = quote!(<#self_ty>::#fn_ident);
+    let boxed = returns_self(self_ty, &sig.decl.output);
+
+    match receiver {
+        None => {
+            let body = if boxed {
+                quote!(Box::new(#call(#(#args),*)))
+            } else {
+                quote!(#call(#(#args),*))
+            };
+
+            quote!(builder.add_function(#name, |#pattern| #body);)
+        }
+        Some(mutable) => {
+            let body = if boxed {
+                quote!(Box::new(#call(this, #(#args),*)))
+            } else {
+                quote!(#call(this, #(#args),*))
+            };
+
+            if mutable {
+                quote!(builder.add_method_mut(#name, |this: &mut #self_ty, #pattern| #body);)
+            } else {
+                quote!(builder.add_method(#name, |this: &#self_ty, #pattern| #body);)
+            }
+        }
+    }
+}
+
+fn returns_self(self_ty: &Type, output: &ReturnType) -> bool {
+    match *output {
+        ReturnType::Default => false,
+        ReturnType::Type(_, ref ty) => {
+            let ty = quote!(#ty).to_string();
+
+            ty == "Self" || ty == quote!(#self_ty).to_string()
+        }
+    }
+}
@@ -0,0 +1,104 @@
+extern crate gsf;
+#[macro_use]
+extern crate gsf_derive;
+
+use gsf::{Builder, ThreadSafe, TyBuilder, Value, ValueTy};
+
+#[derive(Clone, Gsf)]
+struct Player {
+    health: i32,
+    #[gsf(readonly)]
+    level: u8,
+    #[gsf(rename = "displayName")]
+    name: String,
+    #[gsf(skip)]
+    #[allow(dead_code)]
+    secret: u64,
+}
+
+#[gsf_impl]
+impl Player {
+    fn new(name: String) -> Self {
+        Player {
+            health: 100,
+            level: 1,
+            name,
+            secret: 0,
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        self.health > 0
+    }
+
+    #[gsf(rename = "takeDamage")]
+    fn take_damage(&mut self, amount: i32) {
+        self.health -= amount;
+    }
+
+    #[gsf(skip)]
+    #[allow(dead_code)]
+    fn into_name(self) -> String {
+        self.name
+    }
+}
+
+#[test]
+fn derived_ty() {
+    let map = Builder::default()
+        .with_ty(TyBuilder::<Player>::derived().with_items())
+        .finish()
+        .unwrap();
+    let ty = map.values().next().unwrap();
+
+    assert_eq!(ty.ident, "Player");
+
+    let props: Vec<_> = ty.properties
+        .iter()
+        .map(|p| (&*p.ident, p.ty.clone(), p.set.is_some()))
+        .collect();
+    assert_eq!(
+        props,
+        vec![
+            ("health", ValueTy::Int32, true),
+            ("level", ValueTy::Uint8, false),
+            ("displayName", ValueTy::String, true),
+        ]
+    );
+
+    let functions: Vec<_> = ty.functions.iter().map(|f| &*f.ident).collect();
+    assert_eq!(functions, vec!["new"]);
+    let methods: Vec<_> = ty.methods.iter().map(|f| &*f.ident).collect();
+    assert_eq!(methods, vec!["is_alive", "takeDamage"]);
+
+    let player = match (ty.functions[0].exec)(vec![Value::String("Ann".into())]) {
+        Value::Custom(c) => c,
+        other => panic!("Expected player, got {:?}", other),
+    };
+    let shared = Value::Shared(Box::new(::std::rc::Rc::new(::std::cell::RefCell::new(
+        *player.downcast::<Player>().ok().unwrap(),
+    ))));
+    let this = || match shared {
+        Value::Shared(ref s) => Value::Shared(s.share()),
+        _ => unreachable!(),
+    };
+
+    (ty.methods[1].exec)(vec![this(), Value::Int32(100)])
+        .into_res()
+        .unwrap();
+    assert_eq!((ty.methods[0].exec)(vec![this()]), Value::Bool(false));
+    assert_eq!(
+        (ty.properties[2].get.as_ref().unwrap())(vec![this()]),
+        Value::String("Ann".into())
+    );
+}
+
+#[test]
+fn thread_safe_flavour() {
+    let map = Builder::default()
+        .with_ty(TyBuilder::<Player, ThreadSafe>::derived().with_items())
+        .finish_sync()
+        .unwrap();
+
+    assert_eq!(map.values().next().unwrap().properties.len(), 3);
+}
@@ -1,11 +1,46 @@
 extern crate gsf;
 extern crate rlua;
 
+use std::any::TypeId;
+use std::cell::RefCell;
 use std::sync::Arc;
 
+use rlua::{ToLua, ToLuaMulti};
+
 mod util;
 
-struct LuaUd(Box<gsf::Any>);
+thread_local! {
+    static CONTEXT: RefCell<Vec<(*const rlua::Lua, gsf::TyMap)>> = RefCell::new(Vec::new());
+}
+
+struct ContextGuard;
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CONTEXT.with(|c| c.borrow_mut().pop());
+    }
+}
+
+// The pointer is only dereferenced while its entry is on the stack, i.e.
+// while `lua` is still borrowed by the `with_context` call that pushed it.
+pub fn with_context<F, R>(lua: &rlua::Lua, map: &gsf::TyMap, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    CONTEXT.with(|c| c.borrow_mut().push((lua as *const _, map.clone())));
+    let _guard = ContextGuard;
+
+    f()
+}
+
+fn context() -> Option<(*const rlua::Lua, gsf::TyMap)> {
+    CONTEXT.with(|c| c.borrow().last().cloned())
+}
+
+enum LuaUd {
+    Unique(Option<Box<gsf::Any>>),
+    Shared(Box<gsf::Shared>),
+}
 
 impl rlua::UserData for LuaUd {}
 
@@ -17,16 +52,73 @@ struct LuaFunc<'a> {
 
 impl<'a, 'lua> rlua::ToLua<'lua> for LuaFunc<'a> {
     fn to_lua(self, lua: &'lua rlua::Lua) -> rlua::Result<rlua::Value<'lua>> {
-        let func = self.func.exec.clone();
+        let func = self.func.clone();
         let map = self.map;
         let tys = self.tys;
 
         Ok(rlua::Value::Function(lua.create_function(
-            move |lua, val: rlua::MultiValue| lua_func(&func, lua, &map, val, &tys),
+            move |lua, val: rlua::MultiValue| {
+                let val = util::named_args(lua, &map, &func, val)?;
+
+                lua_func(&func.exec, lua, &map, val, &tys)
+            },
         ).unwrap()))
     }
 }
 
+struct LuaOverloads<'a> {
+    ident: String,
+    funcs: Vec<&'a gsf::Function>,
+    map: gsf::TyMap,
+}
+
+impl<'a, 'lua> rlua::ToLua<'lua> for LuaOverloads<'a> {
+    fn to_lua(self, lua: &'lua rlua::Lua) -> rlua::Result<rlua::Value<'lua>> {
+        if self.funcs.len() == 1 {
+            let func = self.funcs[0];
+
+            return LuaFunc {
+                func,
+                tys: func.args.clone(),
+                map: self.map,
+            }.to_lua(lua);
+        }
+
+        let funcs: Vec<gsf::Function> = self.funcs.into_iter().cloned().collect();
+        let ident = self.ident;
+        let map = self.map;
+
+        Ok(rlua::Value::Function(lua.create_function(
+            move |lua, val: rlua::MultiValue| overloaded(&ident, &funcs, lua, &map, val),
+        )?))
+    }
+}
+
+fn group<'a>(funcs: &'a [gsf::Function]) -> Vec<(&'a str, Vec<&'a gsf::Function>)> {
+    let mut groups: Vec<(&str, Vec<&gsf::Function>)> = vec![];
+    for func in funcs {
+        match groups.iter().position(|&(ident, _)| ident == &*func.ident) {
+            Some(i) => groups[i].1.push(func),
+            None => groups.push((&*func.ident, vec![func])),
+        }
+    }
+
+    groups
+}
+
+fn overloaded<'l>(
+    ident: &str,
+    funcs: &[gsf::Function],
+    lua: &'l rlua::Lua,
+    map: &gsf::TyMap,
+    val: rlua::MultiValue<'l>,
+) -> rlua::Result<rlua::Value<'l>> {
+    let found: Vec<_> = val.iter().map(util::lua_ty).collect();
+    let func = gsf::resolve(ident, funcs, &found).map_err(util::to_lua_err)?;
+
+    lua_func(&func.exec, lua, map, val, &func.args)
+}
+
 fn lua_func<'l>(
     fptr: &gsf::FunPtr,
     lua: &'l rlua::Lua,
@@ -34,13 +126,9 @@ fn lua_func<'l>(
     val: rlua::MultiValue<'l>,
     tys: &[gsf::ValueTy],
 ) -> rlua::Result<rlua::Value<'l>> {
-    let res = lua_to_gsf_multi(val, tys, |args| gsf_to_lua(lua, fptr(args), &map));
-
-    if let Err(ref e) = res {
-        eprintln!("Returning error to lua: {}", e);
-    }
-
-    res
+    with_context(lua, map, || {
+        lua_to_gsf_multi(val, tys, |args| gsf_to_lua(lua, fptr(args), &map))
+    })
 }
 
 fn gsf_to_lua<'l>(
@@ -54,20 +142,13 @@ fn gsf_to_lua<'l>(
         gsf::Value::Nil => rlua::Value::Nil,
         gsf::Value::Custom(b) => {
             let id = gsf::Any::type_id(b.as_ref());
-            let ty = map.get(&id).ok_or(rlua::Error::ToLuaConversionError {
-                from: "gsf Value",
-                to: "User data",
-                message: None,
-            })?;
-
-            let methods = &ty.methods;
-            let props = &ty.properties;
-            rlua::Value::UserData(lua.create_userdata_with_methods(
-                LuaUd(b),
-                to_methods(lua, &methods, &props, map),
-            )?)
-        }
-        gsf::Value::Array(a) => {
+            to_user_data(lua, id, LuaUd::Unique(Some(b)), map)?
+        }
+        gsf::Value::Shared(s) => {
+            let id = s.inner_type_id();
+            to_user_data(lua, id, LuaUd::Shared(s), map)?
+        }
+        gsf::Value::Array(a) | gsf::Value::Tuple(a) => {
             let table = lua.create_table()?;
 
             for (i, elem) in a.into_iter().enumerate() {
@@ -76,6 +157,20 @@ fn gsf_to_lua<'l>(
 
             rlua::Value::Table(table)
         }
+        gsf::Value::Map(m) => {
+            let table = lua.create_table()?;
+
+            for (key, elem) in m {
+                table.set(gsf_to_lua(lua, key, map)?, gsf_to_lua(lua, elem, map)?)?;
+            }
+
+            rlua::Value::Table(table)
+        }
+        gsf::Value::Function(f) => LuaFunc {
+            func: &f,
+            tys: f.args.clone(),
+            map: map.clone(),
+        }.to_lua(lua)?,
         gsf::Value::Bool(b) => rlua::Value::Boolean(b),
         gsf::Value::Int8(x) => rlua::Value::Integer(x as i64),
         gsf::Value::Int16(x) => rlua::Value::Integer(x as i64),
@@ -84,20 +179,37 @@ fn gsf_to_lua<'l>(
         gsf::Value::Uint8(x) => rlua::Value::Integer(x as i64),
         gsf::Value::Uint16(x) => rlua::Value::Integer(x as i64),
         gsf::Value::Uint32(x) => rlua::Value::Integer(x as i64),
-        gsf::Value::Uint64(x) => rlua::Value::Integer(x as i64),
+        gsf::Value::Uint64(x) => {
+            let int = <i64 as gsf::FromValue>::from(gsf::Value::Uint64(x));
+            rlua::Value::Integer(int.map_err(util::to_lua_err)?)
+        }
         gsf::Value::Float32(f) => rlua::Value::Number(f as f64),
         gsf::Value::Float64(f) => rlua::Value::Number(f as f64),
-        gsf::Value::Error(e) => {
-            eprintln!("Error: {:?}", e);
-            rlua::Value::Error(util::to_lua_err(e))
-            //rlua::Value::Error(rlua::Error::ExternalError(Arc::new(e)))
-        }
+        gsf::Value::Error(e) => return Err(util::to_lua_err(e)),
         _ => unimplemented!(),
     };
 
     Ok(res)
 }
 
+fn to_user_data<'l>(
+    lua: &'l rlua::Lua,
+    id: TypeId,
+    ud: LuaUd,
+    map: &gsf::TyMap,
+) -> rlua::Result<rlua::Value<'l>> {
+    let ty = map.get(&id).ok_or(rlua::Error::ToLuaConversionError {
+        from: "gsf Value",
+        to: "User data",
+        message: None,
+    })?;
+
+    let methods = to_methods(lua, ty, map);
+    let ud = lua.create_userdata_with_methods(ud, methods)?;
+
+    Ok(rlua::Value::UserData(ud))
+}
+
 fn lua_to_gsf_multi<F, R>(
     multi_val: rlua::MultiValue,
     tys: &[gsf::ValueTy],
@@ -106,31 +218,53 @@ fn lua_to_gsf_multi<F, R>(
 where
     F: FnOnce(Vec<gsf::Value>) -> rlua::Result<R>,
 {
-    util::convert_all(
-        multi_val
-            .into_inner()
-            .into_iter()
-            .zip(tys.iter().cloned())
-            .collect(),
-        f,
-    )
+    let vals = multi_val.into_inner();
+    let rest = match tys.last() {
+        Some(&gsf::ValueTy::Rest(ref inner)) => Some(&**inner),
+        _ => None,
+    };
+    if rest.is_none() && vals.len() > tys.len() {
+        return Err(util::to_lua_err(gsf::Error::WrongArgsNumber {
+            expected: tys.len() as u16,
+            found: vals.len() as u16,
+        }));
+    }
+
+    let tys = tys
+        .iter()
+        .take(tys.len() - rest.map_or(0, |_| 1))
+        .chain(rest.into_iter().cycle());
+
+    util::convert_all(vals.into_iter().zip(tys.cloned()).collect(), f)
 }
 
 fn to_methods<'l>(
     _: &'l rlua::Lua,
-    funcs: &[gsf::Function],
-    props: &[gsf::Property],
+    ty: &gsf::Ty,
     map: &gsf::TyMap,
 ) -> rlua::UserDataMethods<'l, LuaUd> {
+    let ty_ident = &ty.ident;
+    let funcs = &ty.methods;
+    let props = &ty.properties;
     let mut methods = rlua::UserDataMethods::default();
 
-    for method in funcs {
-        let fptr = method.exec.clone();
-        let args = method.args.clone();
-        let map = map.clone();
-        methods.add_function(&method.ident, move |lua, val: rlua::MultiValue| {
-            lua_func(&fptr, lua, &map, val, &args)
-        });
+    for (ident, overloads) in group(funcs) {
+        if overloads.len() == 1 {
+            let method = overloads[0].clone();
+            let map = map.clone();
+            methods.add_function(ident, move |lua, val: rlua::MultiValue| {
+                let val = util::named_args(lua, &map, &method, val)?;
+
+                lua_func(&method.exec, lua, &map, val, &method.args)
+            });
+        } else {
+            let overloads: Vec<gsf::Function> = overloads.into_iter().cloned().collect();
+            let qualified = format!("{}.{}", ty_ident, ident);
+            let map = map.clone();
+            methods.add_function(ident, move |lua, val: rlua::MultiValue| {
+                overloaded(&qualified, &overloads, lua, &map, val)
+            });
+        }
     }
 
     for prop in props {
@@ -164,28 +298,207 @@ fn to_methods<'l>(
         }
     }
 
+    for (op, overloads) in group_operators(&ty.operators) {
+        let qualified = format!("{}.{}", ty_ident, op.ident());
+        let map = map.clone();
+        if op == gsf::Operator::Neg || op == gsf::Operator::Len {
+            methods.add_meta_function(
+                meta_method(op),
+                move |lua, (this, _): (rlua::Value, rlua::Value)| {
+                    let val = this.to_lua_multi(lua)?;
+
+                    overloaded(&qualified, &overloads, lua, &map, val)
+                },
+            );
+        } else {
+            methods.add_meta_function(meta_method(op), move |lua, val: rlua::MultiValue| {
+                overloaded(&qualified, &overloads, lua, &map, val)
+            });
+        }
+    }
+
     methods
 }
 
+fn group_operators(
+    operators: &[(gsf::Operator, gsf::Function)],
+) -> Vec<(gsf::Operator, Vec<gsf::Function>)> {
+    let mut groups: Vec<(gsf::Operator, Vec<gsf::Function>)> = vec![];
+    for &(op, ref func) in operators {
+        match groups.iter().position(|&(o, _)| o == op) {
+            Some(i) => groups[i].1.push(func.clone()),
+            None => groups.push((op, vec![func.clone()])),
+        }
+    }
+
+    groups
+}
+
+fn meta_method(op: gsf::Operator) -> rlua::MetaMethod {
+    match op {
+        gsf::Operator::Add => rlua::MetaMethod::Add,
+        gsf::Operator::Sub => rlua::MetaMethod::Sub,
+        gsf::Operator::Mul => rlua::MetaMethod::Mul,
+        gsf::Operator::Div => rlua::MetaMethod::Div,
+        gsf::Operator::Neg => rlua::MetaMethod::Unm,
+        gsf::Operator::Eq => rlua::MetaMethod::Eq,
+        gsf::Operator::Lt => rlua::MetaMethod::Lt,
+        gsf::Operator::Le => rlua::MetaMethod::Le,
+        gsf::Operator::Index => rlua::MetaMethod::Index,
+        gsf::Operator::NewIndex => rlua::MetaMethod::NewIndex,
+        gsf::Operator::Len => rlua::MetaMethod::Len,
+        gsf::Operator::Concat => rlua::MetaMethod::Concat,
+        gsf::Operator::Call => rlua::MetaMethod::Call,
+        gsf::Operator::ToString => rlua::MetaMethod::ToString,
+    }
+}
+
 fn register_ty(lua: &rlua::Lua, ty: &gsf::Ty, map: &gsf::TyMap) -> rlua::Result<()> {
+    let globals = lua.globals();
+    globals.set(&ty.ident as &str, ty_table(lua, ty, map)?)?;
+
+    Ok(())
+}
+
+fn ty_table<'l>(
+    lua: &'l rlua::Lua,
+    ty: &gsf::Ty,
+    map: &gsf::TyMap,
+) -> rlua::Result<rlua::Table<'l>> {
     let table = lua.create_table()?;
-    for f in &ty.functions {
+    set_functions(&table, &ty.ident, &ty.functions, map)?;
+
+    let mut lazy = vec![];
+    set_constants(lua, &table, &ty.constants, &mut lazy, map)?;
+
+    for variant in &ty.variants {
+        match variant.kind {
+            gsf::VariantKind::Unit(ref make) => {
+                lazy.push((variant.ident.to_string(), make.clone()))
+            }
+            gsf::VariantKind::Constructor(ref func) => table.set(
+                &variant.ident as &str,
+                LuaFunc {
+                    func,
+                    tys: func.args.clone(),
+                    map: map.clone(),
+                },
+            )?,
+        }
+    }
+    if !lazy.is_empty() {
+        set_lazy(lua, &table, lazy, map)?;
+    }
+
+    Ok(table)
+}
+
+fn set_functions<'l>(
+    table: &rlua::Table<'l>,
+    prefix: &str,
+    funcs: &[gsf::Function],
+    map: &gsf::TyMap,
+) -> rlua::Result<()> {
+    for (ident, funcs) in group(funcs) {
         table.set(
-            &f.ident as &str,
-            LuaFunc {
-                func: &f,
+            ident,
+            LuaOverloads {
+                ident: format!("{}.{}", prefix, ident),
+                funcs,
                 map: map.clone(),
-                tys: f.args.clone(),
             },
         )?;
     }
 
-    let globals = lua.globals();
-    globals.set(&ty.ident as &str, table)?;
+    Ok(())
+}
+
+fn set_constants<'l>(
+    lua: &'l rlua::Lua,
+    table: &rlua::Table<'l>,
+    constants: &[gsf::Constant],
+    lazy: &mut Vec<(String, gsf::DefaultPtr)>,
+    map: &gsf::TyMap,
+) -> rlua::Result<()> {
+    for constant in constants {
+        match constant.ty {
+            gsf::ValueTy::Custom => lazy.push((constant.ident.to_string(), constant.value.clone())),
+            _ => table.set(&constant.ident as &str, gsf_to_lua(lua, (constant.value)(), map)?)?,
+        }
+    }
 
     Ok(())
 }
 
+fn set_lazy<'l>(
+    lua: &'l rlua::Lua,
+    table: &rlua::Table<'l>,
+    fields: Vec<(String, gsf::DefaultPtr)>,
+    map: &gsf::TyMap,
+) -> rlua::Result<()> {
+    let map = map.clone();
+    let index = lua.create_function(move |lua, (_, key): (rlua::Table, String)| {
+        match fields.iter().find(|&&(ref ident, _)| *ident == key) {
+            Some(&(_, ref make)) => gsf_to_lua(lua, make(), &map),
+            None => Ok(rlua::Value::Nil),
+        }
+    })?;
+    let meta = lua.create_table()?;
+    meta.set("__index", index)?;
+
+    let set_metatable: rlua::Function = lua.globals().get("setmetatable")?;
+    set_metatable.call::<_, ()>((table.clone(), meta))
+}
+
+fn install<'l>(
+    lua: &'l rlua::Lua,
+    table: &rlua::Table<'l>,
+    module: &gsf::Module,
+    prefix: &str,
+    map: &gsf::TyMap,
+) -> rlua::Result<()> {
+    for id in &module.types {
+        let ty = &map[id];
+        table.set(&ty.ident as &str, ty_table(lua, ty, map)?)?;
+    }
+
+    set_functions(table, prefix, &module.functions, map)?;
+    let mut lazy = vec![];
+    set_constants(lua, table, &module.constants, &mut lazy, map)?;
+    if !lazy.is_empty() {
+        set_lazy(lua, table, lazy, map)?;
+    }
+
+    for nested in &module.modules {
+        let ident: &str = &nested.ident;
+        let nested_table = match table.get::<_, Option<rlua::Table>>(ident)? {
+            Some(existing) => existing,
+            None => {
+                let created = lua.create_table()?;
+                table.set(ident, created.clone())?;
+
+                created
+            }
+        };
+        let nested_prefix = match prefix {
+            "" => ident.to_owned(),
+            _ => format!("{}.{}", prefix, ident),
+        };
+
+        install(lua, &nested_table, nested, &nested_prefix, map)?;
+    }
+
+    Ok(())
+}
+
+pub fn register_modules(
+    context: &rlua::Lua,
+    map: &gsf::TyMap,
+    root: &gsf::Module,
+) -> rlua::Result<()> {
+    install(context, &context.globals(), root, "", map)
+}
+
 pub fn register_with(context: &rlua::Lua, map: &gsf::TyMap) -> rlua::Result<()> {
     for ty in map.values() {
         register_ty(context, ty, map)?;
@@ -193,3 +506,613 @@ pub fn register_with(context: &rlua::Lua, map: &gsf::TyMap) -> rlua::Result<()>
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone)]
+    struct Foo(i32);
+
+    struct Bar;
+
+    #[test]
+    fn panicking_method() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr)))
+                    .with_method("get", |foo, ()| foo.0)
+                    .with_method("explode", |_, ()| -> i32 { panic!("boom") }),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua.eval::<()>(r#"Foo.new(1):explode()"#, Some("explode"));
+        match res {
+            Err(ref e) => assert!(e.to_string().contains("Foo.explode: panicked: boom")),
+            Ok(()) => panic!("Expected error"),
+        }
+
+        let ok = lua
+            .eval::<bool>(
+                r#"
+local foo = Foo.new(3)
+local ok = pcall(function() foo:explode() end)
+return not ok and foo:get() == 3
+"#,
+                Some("recover"),
+            )
+            .unwrap();
+        assert!(ok);
+    }
+
+    #[test]
+    fn variadic() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("max", |(a, rest): (i32, gsf::Rest<i32>)| {
+                        rest.iter().fold(a, |m, &x| m.max(x))
+                    })
+                    .with_function("neg", |(a,): (i32,)| -a),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let max = lua
+            .eval::<i64>(r#"return Foo.max(1) + Foo.max(3, 9, 4)"#, Some("max"))
+            .unwrap();
+        assert_eq!(max, 10);
+
+        match lua.eval::<()>(r#"Foo.neg(1, 2)"#, Some("extra")) {
+            Err(ref e) => assert!(e.to_string().contains("Expected 1 arguments, got 2")),
+            Ok(()) => panic!("Expected error"),
+        }
+        match lua.eval::<()>(r#"Foo.max()"#, Some("missing")) {
+            Err(ref e) => assert!(e.to_string().contains("Expected 1 arguments, got 0")),
+            Ok(()) => panic!("Expected error"),
+        }
+    }
+
+    #[test]
+    fn named_arguments() {
+        use std::collections::HashMap;
+
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function_named(
+                        "spawn",
+                        &["x", "y", "layer"],
+                        |(x, y, layer): (i32, i32, Option<i32>)| {
+                            x * 100 + y * 10 + layer.unwrap_or(0)
+                        },
+                    )
+                    .with_function_named(
+                        "count",
+                        &["items"],
+                        |(items,): (HashMap<String, i32>,)| items.len() as i32,
+                    ),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua
+            .eval::<i64>(
+                r#"return Foo.spawn{ y = 2, x = 1 } + Foo.spawn(1, { layer = 3, y = 2 })"#,
+                Some("named"),
+            )
+            .unwrap();
+        assert_eq!(res, 120 + 123);
+
+        match lua.eval::<()>(r#"Foo.spawn{ x = 1, z = 2 }"#, Some("unknown")) {
+            Err(ref e) => assert!(e.to_string().contains("spawn: unknown argument z")),
+            Ok(()) => panic!("Expected error"),
+        }
+
+        let count = lua
+            .eval::<i64>(r#"return Foo.count{ a = 1, b = 2 }"#, Some("positional"))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn overloads() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("new", |()| Box::new(Foo(0)))
+                    .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr)))
+                    .with_function("new", |(a, b): (i32, i32)| Box::new(Foo(a + b)))
+                    .with_method("get", |foo, ()| foo.0),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let sum = lua
+            .eval::<i64>(
+                r#"return Foo.new():get() + Foo.new(2):get() + Foo.new(3, 4):get()"#,
+                Some("overloads"),
+            )
+            .unwrap();
+        assert_eq!(sum, 9);
+
+        match lua.eval::<()>(r#"Foo.new("x")"#, Some("no overload")) {
+            Err(ref e) => assert!(e.to_string().contains("Foo.new: no overload matches (String)")),
+            Ok(()) => panic!("Expected error"),
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum KeyCode {
+        Escape,
+        Enter,
+    }
+
+    enum Shape {
+        Circle { r: f32 },
+    }
+
+    #[test]
+    fn enums() {
+        let shape = gsf::TyBuilder::<Shape>::new("Shape").with_method("radius", |shape, ()| {
+            match *shape {
+                Shape::Circle { r } => r,
+            }
+        });
+        let map = gsf::Builder::default()
+            .with_enum(
+                gsf::EnumBuilder::<KeyCode>::new("KeyCode")
+                    .with_unit("Escape", KeyCode::Escape)
+                    .with_unit("Enter", KeyCode::Enter),
+            )
+            .with_enum(
+                gsf::EnumBuilder::from_ty(shape)
+                    .with_variant("Circle", |(r,): (f32,)| Shape::Circle { r })
+                    .with_variant_of(|_| "Circle"),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let ok = lua
+            .eval::<bool>(
+                r#"
+local key = KeyCode.Enter
+return key:variant() == "Enter"
+    and key:to_int() == 1
+    and KeyCode.from_name("Escape"):to_int() == 0
+    and KeyCode.from_int(7) == nil
+    and KeyCode.Missing == nil
+    and Shape.Circle(2):variant() == "Circle"
+    and Shape.Circle(2):radius() == 2
+"#,
+                Some("enums"),
+            )
+            .unwrap();
+        assert!(ok);
+    }
+
+    struct Wrapper(Foo);
+
+    impl ::std::ops::Deref for Wrapper {
+        type Target = Foo;
+
+        fn deref(&self) -> &Foo {
+            &self.0
+        }
+    }
+
+    impl ::std::ops::DerefMut for Wrapper {
+        fn deref_mut(&mut self) -> &mut Foo {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn parent_methods() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_method("get", |foo, ()| foo.0)
+                    .with_property(
+                        gsf::PropertyBuilder::new("value")
+                            .with_setter(|foo: &mut Foo, val: i32| foo.0 = val),
+                    ),
+            )
+            .with_ty(
+                gsf::TyBuilder::<Wrapper>::new("Wrapper")
+                    .with_parent()
+                    .with_function("new", |(nr,): (i32,)| Box::new(Wrapper(Foo(nr)))),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let value = lua
+            .eval::<i64>(
+                r#"
+local w = Wrapper.new(1)
+w:setValue(5)
+return w:get()
+"#,
+                Some("parent"),
+            )
+            .unwrap();
+        assert_eq!(value, 5);
+    }
+
+    #[test]
+    fn operators() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr)))
+                    .with_method("get", |foo, ()| foo.0)
+                    .with_operator(gsf::Operator::Add, |foo, (x,): (i32,)| Box::new(Foo(foo.0 + x)))
+                    .with_operator(gsf::Operator::Neg, |foo, ()| Box::new(Foo(-foo.0)))
+                    .with_operator(gsf::Operator::ToString, |foo, ()| format!("Foo({})", foo.0)),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua
+            .eval::<String>(r#"return tostring(-(Foo.new(1) + 4))"#, Some("operators"))
+            .unwrap();
+        assert_eq!(res, "Foo(-5)");
+    }
+
+    #[test]
+    fn constants() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_method_mut("add", |foo, (x,): (i32,)| foo.0 += x)
+                    .with_method("get", |foo, ()| foo.0)
+                    .with_constant("MAX", 10)
+                    .with_constant("ZERO", Box::new(Foo(0))),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua
+            .eval::<i64>(
+                r#"
+local zero = Foo.ZERO
+zero:add(Foo.MAX)
+return rawget(Foo, "MAX") + zero:get() + Foo.ZERO:get()
+"#,
+                Some("constants"),
+            )
+            .unwrap();
+        assert_eq!(res, 20);
+    }
+
+    #[test]
+    fn modules() {
+        let (map, root) = gsf::Builder::default()
+            .with_module(
+                gsf::ModuleBuilder::new("engine")
+                    .with_constant("VERSION", 3)
+                    .with_module(
+                        gsf::ModuleBuilder::new("audio")
+                            .with_function("play", |(volume,): (i32,)| volume * 2)
+                            .with_ty(
+                                gsf::TyBuilder::<Foo>::new("Sound")
+                                    .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr)))
+                                    .with_method("get", |foo, ()| foo.0),
+                            ),
+                    ),
+            )
+            .finish_with_modules()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_modules(&lua, &map, &root).unwrap();
+
+        let res = lua
+            .eval::<i64>(
+                r#"
+assert(Sound == nil)
+return engine.VERSION + engine.audio.play(2) + engine.audio.Sound.new(5):get()
+"#,
+                Some("modules"),
+            )
+            .unwrap();
+        assert_eq!(res, 3 + 4 + 5);
+    }
+
+    #[test]
+    fn coercion() {
+        let lua_with = |coercion| {
+            let map = gsf::Builder::default()
+                .with_ty(
+                    gsf::TyBuilder::<Foo>::new("Foo")
+                        .with_function("half", |(x,): (i32,)| x / 2)
+                        .with_function("big", |()| ::std::u64::MAX),
+                )
+                .with_coercion(coercion)
+                .finish()
+                .unwrap();
+            let lua = rlua::Lua::new();
+            register_with(&lua, &map).unwrap();
+
+            lua
+        };
+
+        let lua = lua_with(gsf::Coercion::strict());
+        assert_eq!(lua.eval::<i64>("return Foo.half(8)", None).unwrap(), 4);
+        match lua.eval::<i64>("return Foo.half(5.5)", None) {
+            Err(ref e) => assert!(e.to_string().contains("expected Int32, found Float64")),
+            Ok(x) => panic!("Expected error, got {}", x),
+        }
+
+        let lua = lua_with(gsf::Coercion::lenient());
+        assert_eq!(lua.eval::<i64>("return Foo.half(5.5)", None).unwrap(), 2);
+        match lua.eval::<i64>("return Foo.big()", None) {
+            Err(ref e) => assert!(e.to_string().contains(&::std::u64::MAX.to_string())),
+            Ok(x) => panic!("Expected error, got {}", x),
+        }
+    }
+
+    #[test]
+    fn wrong_user_data() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr)))
+                    .with_method("absorb", |foo, (other,): (Box<Foo>,)| foo.0 + other.0)
+                    .with_method("merge", |foo, (other, scale): (Box<Foo>, i32)| {
+                        foo.0 + other.0 * scale
+                    }),
+            )
+            .with_ty(
+                gsf::TyBuilder::<Bar>::new("Bar")
+                    .with_function("new", |()| Box::new(Bar))
+                    .with_method("ping", |_, ()| true),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        match lua.eval::<i64>("return Foo.new(1):absorb(Bar.new())", Some("wrong")) {
+            Err(ref e) => {
+                let message = e.to_string();
+                assert!(message.contains("Foo.absorb: argument 1"), "{}", message);
+                assert!(message.contains("Bar"), "{}", message);
+            }
+            Ok(x) => panic!("Expected error, got {}", x),
+        }
+
+        let chunk = r#"
+            local bar, other = Bar.new(), Foo.new(2)
+            assert(not pcall(function() return Foo.new(1):absorb(bar) end))
+            assert(not pcall(function() return Foo.new(1):merge(other, 1.5) end))
+            assert(bar:ping())
+            return Foo.new(1):absorb(other)
+        "#;
+        assert_eq!(lua.eval::<i64>(chunk, Some("kept")).unwrap(), 3);
+    }
+
+    #[test]
+    fn moved_user_data() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr)))
+                    .with_function("consume", |(foo,): (Box<Foo>,)| foo.0)
+                    .with_method("get", |foo, ()| foo.0),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua.eval::<i64>(
+            r#"
+local foo = Foo.new(4)
+assert(Foo.consume(foo) == 4)
+return foo:get()
+"#,
+            Some("moved"),
+        );
+        match res {
+            Err(ref e) => assert!(e.to_string().contains("value was moved"), "{}", e),
+            Ok(x) => panic!("Expected error, got {}", x),
+        }
+    }
+
+    #[test]
+    fn shared_handle() {
+        use std::rc::Rc;
+
+        let foo = Rc::new(RefCell::new(Foo(1)));
+        let handle = foo.clone();
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("shared", move |()| handle.clone())
+                    .with_method("get", |foo, ()| foo.0)
+                    .with_method_mut("set", |foo, (nr,): (i32,)| foo.0 = nr),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua
+            .eval::<i64>(
+                r#"
+local a, b = Foo.shared(), Foo.shared()
+a:set(5)
+return b:get()
+"#,
+                Some("shared"),
+            )
+            .unwrap();
+        assert_eq!(res, 5);
+        assert_eq!(foo.borrow().0, 5);
+    }
+
+    #[test]
+    fn tables() {
+        use std::collections::HashMap;
+
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("double", |(m,): (HashMap<String, i32>,)| {
+                        m.into_iter().map(|(k, v)| (k, v * 2)).collect::<HashMap<_, _>>()
+                    })
+                    .with_function("reverse", |(m,): (HashMap<String, Vec<Vec<i32>>>,)| {
+                        m.into_iter()
+                            .map(|(k, mut rows)| {
+                                rows.reverse();
+                                (k, rows)
+                            })
+                            .collect::<HashMap<_, _>>()
+                    }),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let ok = lua
+            .eval::<bool>(
+                r#"
+local m = Foo.double({ a = 1, b = 2 })
+local n = Foo.reverse({ grid = { { 1, 2 }, { 3 } }, empty = {} })
+return m.a == 2 and m.b == 4
+    and #n.grid == 2 and n.grid[1][1] == 3 and n.grid[2][2] == 2
+    and #n.empty == 0
+"#,
+                Some("tables"),
+            )
+            .unwrap();
+        assert!(ok);
+    }
+
+    #[test]
+    fn stored_callback() {
+        use std::rc::Rc;
+
+        type Timer = gsf::Callback<(i32,), i32>;
+
+        let timers: Rc<RefCell<Vec<Timer>>> = Rc::default();
+        let stored = timers.clone();
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("after", move |(cb,): (Timer,)| stored.borrow_mut().push(cb)),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        lua.eval::<()>("Foo.after(function(dt) return dt * 10 end)", Some("after"))
+            .unwrap();
+
+        let timer = timers.borrow()[0].clone();
+        assert_eq!(timer.function().args, vec![gsf::ValueTy::Int32]);
+        assert!(timer.call((3,)).is_err());
+        assert_eq!(with_context(&lua, &map, || timer.call((3,))).unwrap(), 30);
+
+        let other = rlua::Lua::new();
+        assert!(with_context(&other, &map, || timer.call((3,))).is_err());
+    }
+
+    #[test]
+    fn returned_closure() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo").with_function("adder", |(n,): (i32,)| {
+                    Box::new(move |(x,): (i32,)| x + n) as Box<Fn((i32,)) -> i32>
+                }),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua
+            .eval::<i64>("local add = Foo.adder(3) return add(4) + add(10)", Some("closure"))
+            .unwrap();
+        assert_eq!(res, 7 + 13);
+    }
+
+    #[test]
+    fn returned_error() {
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("parse", |(s,): (String,)| s.parse::<i32>()),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        assert_eq!(lua.eval::<i64>(r#"return Foo.parse("12")"#, None).unwrap(), 12);
+        match lua.eval::<i64>(r#"return Foo.parse("twelve")"#, Some("parse")) {
+            Err(ref e) => {
+                let message = e.to_string();
+                assert!(
+                    message.contains("Foo.parse: invalid digit found in string"),
+                    "{}",
+                    message
+                );
+            }
+            Ok(x) => panic!("Expected error, got {}", x),
+        }
+    }
+
+    trait Describe {
+        fn describe(&self) -> String;
+    }
+
+    impl Describe for Foo {
+        fn describe(&self) -> String {
+            format!("foo {}", self.0)
+        }
+    }
+
+    gsf::interface!(Describe: Foo);
+
+    #[test]
+    fn interface_methods() {
+        let describe = gsf::InterfaceBuilder::<Describe>::new("Describe")
+            .with_method("describe", |this, ()| this.describe());
+        let map = gsf::Builder::default()
+            .with_ty(
+                gsf::TyBuilder::<Foo>::new("Foo")
+                    .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr)))
+                    .with_interface(&describe),
+            )
+            .finish()
+            .unwrap();
+        let lua = rlua::Lua::new();
+        register_with(&lua, &map).unwrap();
+
+        let res = lua
+            .eval::<String>("return Foo.new(2):describe()", Some("interface"))
+            .unwrap();
+        assert_eq!(res, "foo 2");
+    }
+}
@@ -10,6 +10,7 @@ fn register(context: &rlua::Lua) -> rlua::Result<()> {
             gsf::TyBuilder::<Foo>::new("Foo")
                 .with_function("new", |(nr,): (i32,)| Box::new(Foo(nr as i32)))
                 .with_function("sum_up", |(a, b): (i32, i32)| a + b)
+                .with_function("sum_up", |(a, b, c): (i32, i32, i32)| a + b + c)
                 .with_method("foo_sq", |foo, ()| foo.0 * foo.0)
                 .with_property(
                     gsf::PropertyBuilder::new("value")
@@ -17,7 +18,8 @@ fn register(context: &rlua::Lua) -> rlua::Result<()> {
                         .with_setter(|this: &mut Foo, val: i32| this.0 = val),
                 ),
         )
-        .finish();
+        .finish()
+        .expect("ambiguous overloads");
     gsf_rlua::register_with(context, &map)?;
 
     Ok(())
@@ -28,6 +30,7 @@ fn run() -> rlua::Result<()> {
     register(&context)?;
 
     context.eval::<()>(r#"print(Foo.sum_up(1, 9))"#, Some("sum"))?;
+    context.eval::<()>(r#"print(Foo.sum_up(1, 2, 3))"#, Some("sum3"))?;
     context.eval::<()>(r#"
 Foo.sum_up(1) -- Does not work yet
 print(Foo.sum_up(1, 1))
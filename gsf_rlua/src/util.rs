@@ -12,6 +12,16 @@ pub fn to_lua_err(err: gsf::Error) -> rlua::Error {
             to: "self",
             message: None,
         },
+        gsf::Error::ValueMoved => rlua::Error::FromLuaConversionError {
+            from: "user data",
+            to: "Rust value",
+            message: Some("value was moved".to_owned()),
+        },
+        gsf::Error::AlreadyBorrowed { ty } => rlua::Error::FromLuaConversionError {
+            from: "user data",
+            to: "Rust value",
+            message: Some(format!("{} is already borrowed", ty)),
+        },
         gsf::Error::WrongArgsNumber { expected, found } => rlua::Error::FromLuaConversionError {
             from: "missing argument",
             to: "expected arguments",
@@ -22,7 +32,147 @@ pub fn to_lua_err(err: gsf::Error) -> rlua::Error {
             to: "Rust value",
             message: Some(format!("Expected {:?}, got {:?}", expected, found)),
         },
-        gsf::Error::WrongAny { .. } => unreachable!("This should never happen"),
+        gsf::Error::WrongAny { expected, found } => rlua::Error::FromLuaConversionError {
+            from: found,
+            to: expected,
+            message: None,
+        },
+        gsf::Error::WrongLength { expected, found } => rlua::Error::FromLuaConversionError {
+            from: "table",
+            to: "Rust value",
+            message: Some(format!("Expected {} elements, got {}", expected, found)),
+        },
+        gsf::Error::LossyConversion { value, expected } => rlua::Error::FromLuaConversionError {
+            from: "number",
+            to: "Rust number",
+            message: Some(format!("Cannot convert {} to {:?} without loss", value, expected)),
+        },
+        gsf::Error::NotCloneable { ty } => rlua::Error::FromLuaConversionError {
+            from: "user data",
+            to: "Rust value",
+            message: Some(format!("{} cannot be cloned", ty)),
+        },
+        gsf::Error::NotHashable { found } => rlua::Error::FromLuaConversionError {
+            from: "lua value",
+            to: "Rust value",
+            message: Some(format!("{:?} cannot be hashed", found)),
+        },
+        gsf::Error::WrongEntry { key, error } => {
+            prefixed(to_lua_err(*error), format!("Entry {}", key))
+        }
+        gsf::Error::Script { message } => rlua::Error::RuntimeError(message.into_owned()),
+        gsf::Error::Host { message, sources } => {
+            let mut message = message.into_owned();
+            for source in sources {
+                message = format!("{}\ncaused by: {}", message, source);
+            }
+
+            rlua::Error::RuntimeError(message)
+        }
+        gsf::Error::WrongElement { index, error } => {
+            prefixed(to_lua_err(*error), format!("Element {}", index + 1))
+        }
+        gsf::Error::Argument { index, name, error } => match name {
+            Some(name) => prefixed(to_lua_err(*error), format!("argument {} ({})", index, name)),
+            None => prefixed(to_lua_err(*error), format!("argument {}", index)),
+        },
+        gsf::Error::Call { ident, error } => prefixed(to_lua_err(*error), ident.into_owned()),
+        gsf::Error::Panicked { message } => {
+            rlua::Error::RuntimeError(format!("panicked: {}", message))
+        }
+        err @ gsf::Error::AmbiguousOverload { .. }
+        | err @ gsf::Error::NoOverload { .. }
+        | err @ gsf::Error::UnknownArgument { .. }
+        | err @ gsf::Error::MissingArgument { .. }
+        | err @ gsf::Error::DuplicateArgument { .. }
+        | err @ gsf::Error::MissingParent { .. }
+        | err @ gsf::Error::ParentCycle { .. } => rlua::Error::RuntimeError(err.to_string()),
+    }
+}
+
+pub fn lua_ty(val: &rlua::Value) -> ValueTy {
+    match *val {
+        Value::Nil => ValueTy::Option(Box::new(ValueTy::Unknown)),
+        Value::Boolean(_) => ValueTy::Bool,
+        Value::Integer(_) => ValueTy::Int64,
+        Value::Number(_) => ValueTy::Float64,
+        Value::String(_) => ValueTy::String,
+        Value::Table(_) => ValueTy::Map(Box::new(ValueTy::Unknown), Box::new(ValueTy::Unknown)),
+        Value::Function(_) => ValueTy::Function(vec![], Box::new(ValueTy::Unknown)),
+        Value::UserData(_) => ValueTy::Custom,
+        _ => ValueTy::Unknown,
+    }
+}
+
+pub fn named_args<'l>(
+    lua: &'l rlua::Lua,
+    map: &gsf::TyMap,
+    func: &gsf::Function,
+    val: rlua::MultiValue<'l>,
+) -> rlua::Result<rlua::MultiValue<'l>> {
+    let mut vals = val.into_inner();
+    let table = match vals.back() {
+        Some(&Value::Table(ref t)) if takes_named(func, vals.len() - 1) => t.clone(),
+        _ => return Ok(vals.into_iter().collect()),
+    };
+
+    let mut named = vec![];
+    for pair in table.pairs::<Value, Value>() {
+        match pair? {
+            (Value::String(key), val) => {
+                named.push((gsf::Str::from(key.to_str()?.to_owned()), val))
+            }
+            _ => return Ok(vals.into_iter().collect()),
+        }
+    }
+    vals.pop_back();
+
+    let default = |val| {
+        gsf_to_lua(lua, val, map).map_err(|e| gsf::Error::Script {
+            message: e.to_string().into(),
+        })
+    };
+
+    func.order_named(vals.into_iter().collect(), named, || Value::Nil, default)
+        .map(|ordered| ordered.into_iter().collect())
+        .map_err(|e| {
+            to_lua_err(gsf::Error::Call {
+                ident: func.ident.clone(),
+                error: Box::new(e),
+            })
+        })
+}
+
+fn takes_named(func: &gsf::Function, index: usize) -> bool {
+    if func.arg_names.is_empty() {
+        return false;
+    }
+
+    let ty = match func.args.get(index) {
+        Some(&ValueTy::Option(ref inner)) => &**inner,
+        Some(ty) => ty,
+        None => return false,
+    };
+
+    match *ty {
+        ValueTy::Tuple(_) | ValueTy::Array(_) | ValueTy::Map(..) | ValueTy::Rest(_) => false,
+        _ => true,
+    }
+}
+
+fn prefixed(err: rlua::Error, prefix: String) -> rlua::Error {
+    match err {
+        rlua::Error::FromLuaConversionError { from, to, message } => {
+            rlua::Error::FromLuaConversionError {
+                from,
+                to,
+                message: Some(format!("{}: {}", prefix, message.unwrap_or_default())),
+            }
+        }
+        rlua::Error::RuntimeError(message) => {
+            rlua::Error::RuntimeError(format!("{}: {}", prefix, message))
+        }
+        other => other,
     }
 }
 
@@ -39,128 +189,230 @@ where
                 message: Some(format!("Expected boolean, got {:?}", other)),
             }),
         },
-        ValueTy::Int8 => match val {
-            Value::Integer(i) => f(gsf::Value::Int8(i as i8)),
-            other => Err(rlua::Error::FromLuaConversionError {
-                from: "value (TODO: use Value::type_name())",
-                to: "integer 8-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
-            }),
-        },
-        ValueTy::Int16 => match val {
-            Value::Integer(i) => f(gsf::Value::Int16(i as i16)),
-            other => Err(rlua::Error::FromLuaConversionError {
-                from: "value (TODO: use Value::type_name())",
-                to: "integer 16-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
-            }),
-        },
-        ValueTy::Int32 => match val {
-            Value::Integer(i) => f(gsf::Value::Int32(i as i32)),
-            other => Err(rlua::Error::FromLuaConversionError {
-                from: "value (TODO: use Value::type_name())",
-                to: "integer 32-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
-            }),
-        },
-        ValueTy::Int64 => match val {
-            Value::Integer(i) => f(gsf::Value::Int64(i as i64)),
+        ValueTy::Int8
+        | ValueTy::Int16
+        | ValueTy::Int32
+        | ValueTy::Int64
+        | ValueTy::Uint8
+        | ValueTy::Uint16
+        | ValueTy::Uint32
+        | ValueTy::Uint64
+        | ValueTy::Float32
+        | ValueTy::Float64 => {
+            let nr = match val {
+                Value::Integer(i) => gsf::Value::Int64(i),
+                Value::Number(nr) => gsf::Value::Float64(nr),
+                other => {
+                    return Err(rlua::Error::FromLuaConversionError {
+                        from: "value (TODO: use Value::type_name())",
+                        to: "number",
+                        message: Some(format!("Expected number, got {:?}", other)),
+                    })
+                }
+            };
+
+            f(nr)
+        }
+        ValueTy::CustomRef => match val {
+            Value::UserData(ud) => {
+                let ud = ud.borrow::<LuaUd>()?;
+                match *ud {
+                    LuaUd::Unique(Some(ref b)) => f(gsf::Value::CustomRef(b.as_ref())),
+                    LuaUd::Unique(None) => Err(to_lua_err(gsf::Error::ValueMoved)),
+                    LuaUd::Shared(ref s) => s.with_ref(|any| f(gsf::Value::CustomRef(any)))
+                        .map_err(to_lua_err)?,
+                }
+            }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "integer 64-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
+                to: "custom ref",
+                message: Some(format!("Expected custom ref, got {:?}", other)),
             }),
         },
-        ValueTy::Uint8 => match val {
-            Value::Integer(i) => f(gsf::Value::Uint8(i as u8)),
+        ValueTy::CustomMut => match val {
+            Value::UserData(ud) => {
+                let mut ud = ud.borrow_mut::<LuaUd>()?;
+                match *ud {
+                    LuaUd::Unique(Some(ref mut b)) => f(gsf::Value::CustomMut(b.as_mut())),
+                    LuaUd::Unique(None) => Err(to_lua_err(gsf::Error::ValueMoved)),
+                    LuaUd::Shared(ref s) => s.with_mut(|any| f(gsf::Value::CustomMut(any)))
+                        .map_err(to_lua_err)?,
+                }
+            }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "unsigned integer 8-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
+                to: "custom mut",
+                message: Some(format!("Expected custom mut, got {:?}", other)),
             }),
         },
-        ValueTy::Uint16 => match val {
-            Value::Integer(i) => f(gsf::Value::Uint16(i as u16)),
+        ValueTy::String => match val {
+            Value::String(s) => f(gsf::Value::String(s.to_str()?.to_owned().into())),
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "unsigned integer 16-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
+                to: "string",
+                message: Some(format!("Expected string, got {:?}", other)),
             }),
         },
-        ValueTy::Uint32 => match val {
-            Value::Integer(i) => f(gsf::Value::Uint32(i as u32)),
-            other => Err(rlua::Error::FromLuaConversionError {
-                from: "value (TODO: use Value::type_name())",
-                to: "unsigned integer 32-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
-            }),
+        ValueTy::Option(o) => match val {
+            Value::Nil => f(gsf::Value::Nil),
+            other => map(other, *o, f),
         },
-        ValueTy::Uint64 => match val {
-            Value::Integer(i) => f(gsf::Value::Uint64(i as u64)),
+        ValueTy::Array(elem) => match val {
+            Value::Table(t) => {
+                let elems = t.sequence_values::<Value>()
+                    .map(|v| v.map(|v| (v, (*elem).clone())))
+                    .collect::<rlua::Result<_>>()?;
+
+                convert_all(elems, |a| f(gsf::Value::Array(a)))
+            }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "unsigned integer 64-bit",
-                message: Some(format!("Expected integer, got {:?}", other)),
+                to: "array",
+                message: Some(format!("Expected table, got {:?}", other)),
             }),
         },
-        ValueTy::Float32 => match val {
-            Value::Number(nr) => f(gsf::Value::Float32(nr as f32)),
+        ValueTy::Tuple(tys) => match val {
+            Value::Table(t) => {
+                let elems = t.sequence_values::<Value>().collect::<rlua::Result<Vec<_>>>()?;
+                if elems.len() != tys.len() {
+                    return Err(to_lua_err(gsf::Error::WrongLength {
+                        expected: tys.len(),
+                        found: elems.len(),
+                    }));
+                }
+
+                convert_all(elems.into_iter().zip(tys).collect(), |t| {
+                    f(gsf::Value::Tuple(t))
+                })
+            }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "float 32-bit",
-                message: Some(format!("Expected float, got {:?}", other)),
+                to: "tuple",
+                message: Some(format!("Expected table, got {:?}", other)),
             }),
         },
-        ValueTy::Float64 => match val {
-            Value::Number(nr) => f(gsf::Value::Float64(nr as f64)),
+        ValueTy::Custom => match val {
+            Value::UserData(ud) => {
+                let mut ud = ud.borrow_mut::<LuaUd>()?;
+                match *ud {
+                    LuaUd::Unique(None) => Err(to_lua_err(gsf::Error::ValueMoved)),
+                    LuaUd::Unique(ref mut slot) => f(gsf::Value::CustomSlot(slot)),
+                    LuaUd::Shared(_) => Err(to_lua_err(gsf::Error::WrongType {
+                        expected: ValueTy::Custom,
+                        found: ValueTy::Shared,
+                    })),
+                }
+            }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "float 64-bit",
-                message: Some(format!("Expected float, got {:?}", other)),
+                to: "custom",
+                message: Some(format!("Expected custom, got {:?}", other)),
             }),
         },
-        ValueTy::CustomRef => match val {
-            Value::UserData(ud) => {
-                let ud = ud.borrow::<LuaUd>()?;
-                f(gsf::Value::CustomRef(ud.0.as_ref()))
+        ValueTy::Map(key, elem) => match val {
+            Value::Table(t) => {
+                let mut entries = VecDeque::new();
+                for pair in t.pairs::<Value, Value>() {
+                    let (k, v) = pair?;
+                    entries.push_back((k, (*key).clone()));
+                    entries.push_back((v, (*elem).clone()));
+                }
+
+                convert_all(entries, |flat| {
+                    let mut flat = flat.into_iter();
+                    let mut m = Vec::with_capacity(flat.len() / 2);
+                    while let (Some(k), Some(v)) = (flat.next(), flat.next()) {
+                        m.push((k, v));
+                    }
+
+                    f(gsf::Value::Map(m))
+                })
             }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "custom ref",
-                message: Some(format!("Expected custom ref, got {:?}", other)),
+                to: "map",
+                message: Some(format!("Expected table, got {:?}", other)),
             }),
         },
-        ValueTy::CustomMut => match val {
+        ValueTy::Shared => match val {
             Value::UserData(ud) => {
-                let mut ud = ud.borrow_mut::<LuaUd>()?;
-                f(gsf::Value::CustomMut(ud.0.as_mut()))
+                let s = match *ud.borrow::<LuaUd>()? {
+                    LuaUd::Shared(ref s) => s.share(),
+                    LuaUd::Unique(_) => {
+                        return Err(to_lua_err(gsf::Error::WrongType {
+                            expected: ValueTy::Shared,
+                            found: ValueTy::Custom,
+                        }))
+                    }
+                };
+
+                f(gsf::Value::Shared(s))
             }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "custom mut",
-                message: Some(format!("Expected custom mut, got {:?}", other)),
+                to: "shared",
+                message: Some(format!("Expected shared, got {:?}", other)),
             }),
         },
-        ValueTy::String => match val {
-            Value::String(s) => f(gsf::Value::String(s.to_str()?.to_owned().into())),
+        ValueTy::Function(args, ret) => match val {
+            Value::Function(func) => {
+                let (lua, _) = context().ok_or_else(no_context)?;
+                let key = unsafe { &*lua }.create_registry_value(func)?;
+                let ret = *ret;
+
+                f(gsf::Value::Function(gsf::Function {
+                    exec: Arc::new(move |args: Vec<gsf::Value>| {
+                        // The Lua state is looked up on every call; a context of a
+                        // different state is rejected by `registry_value`.
+                        context()
+                            .ok_or_else(no_context)
+                            .and_then(|(lua, ty_map)| {
+                                call_registered(unsafe { &*lua }, &ty_map, &key, &ret, args)
+                            })
+                            .map_err(|e| gsf::Error::Script {
+                                message: e.to_string().into(),
+                            })
+                            .into()
+                    }),
+                    ident: "<lua function>".into(),
+                    args,
+                    arg_names: vec![],
+                    defaults: vec![],
+                    ret: ret.clone(),
+                }))
+            }
             other => Err(rlua::Error::FromLuaConversionError {
                 from: "value (TODO: use Value::type_name())",
-                to: "string",
-                message: Some(format!("Expected string, got {:?}", other)),
+                to: "function",
+                message: Some(format!("Expected function, got {:?}", other)),
             }),
         },
-        ValueTy::Option(o) => match val {
-            Value::Nil => f(gsf::Value::Nil),
-            other => map(other, *o, f),
-        },
-        ValueTy::Void => unimplemented!(),
-        ValueTy::Custom => unimplemented!(),
-        ValueTy::Array(_) => unimplemented!(),
-        ValueTy::Tuple(_) => unimplemented!(),
+        ValueTy::Void => f(gsf::Value::Void),
+        ValueTy::Rest(inner) => map(val, *inner, f),
         ValueTy::Unknown => unimplemented!(),
     }
 }
 
+fn call_registered(
+    lua: &rlua::Lua,
+    ty_map: &gsf::TyMap,
+    key: &rlua::RegistryKey,
+    ret: &ValueTy,
+    args: Vec<gsf::Value>,
+) -> rlua::Result<gsf::Value<'static>> {
+    let func: rlua::Function = lua.registry_value(key)?;
+    let args = args.into_iter()
+        .map(|arg| gsf_to_lua(lua, arg, ty_map))
+        .collect::<rlua::Result<rlua::MultiValue>>()?;
+    let res: rlua::Value = func.call(args)?;
+
+    map(res, ret.clone(), |val| val.into_owned().map_err(to_lua_err))
+}
+
+fn no_context() -> rlua::Error {
+    rlua::Error::RuntimeError("No active Lua context, use `gsf_rlua::with_context`".to_owned())
+}
+
 pub fn convert_all<F, R>(v: VecDeque<(rlua::Value, gsf::ValueTy)>, f: F) -> rlua::Result<R>
 where
     F: FnOnce(Vec<gsf::Value>) -> rlua::Result<R>,
@@ -58,6 +58,17 @@ impl Any {
             None
         }
     }
+
+    #[inline]
+    pub fn downcast<T: Any>(self: Box<Self>) -> Result<Box<T>, Box<Any>> {
+        if self.is::<T>() {
+            unsafe {
+                Ok(Box::from_raw(Box::into_raw(self) as *mut T))
+            }
+        } else {
+            Err(self)
+        }
+    }
 }
 
 #[inline]
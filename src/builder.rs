@@ -1,117 +1,1480 @@
 use std::any::TypeId;
 use std::marker::PhantomData;
+use std::mem::{self, Discriminant};
+use std::ops::DerefMut;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 
-use conv::{FromMultiValue, FromValue, IntoValue, MultiVal};
-use {Any, Error, Function, Property, Ty, TyMap, TyMapMut, Value};
+use conv::{FromMultiValue, FromValue, IntoMultiValue, IntoValue, MultiVal};
+use interface::{self, Implements, Interface};
+use overload;
+use sync::{Captures, Local, SyncTyMap, ThreadSafe};
+use {type_name_of, Any, Coercion, Constant, DefaultPtr, Error, FunPtr, Function, Module, Operator,
+     Parent, Property, Result, Str, Ty, TyMap, TyMapMut, Value, ValueTy, Variant, VariantKind};
 
 #[must_use]
-#[derive(Default)]
-pub struct Builder {
-    pub map: TyMapMut,
+pub struct Builder<M = Local> {
+    coercion: Coercion,
+    map: TyMapMut,
+    marker: PhantomData<M>,
+    modules: Vec<Module>,
 }
 
-impl Builder {
-    pub fn add_ty<T: Any>(&mut self, builder: TyBuilder<T>) {
+impl<M> Builder<M> {
+    pub fn add_ty<T: Any>(&mut self, builder: TyBuilder<T, M>) {
         self.map.insert(TypeId::of::<T>(), builder.ty);
     }
 
-    pub fn with_ty<T: Any>(mut self, builder: TyBuilder<T>) -> Self {
+    pub fn with_ty<T: Any>(mut self, builder: TyBuilder<T, M>) -> Self {
         self.add_ty(builder);
 
         self
     }
 
-    pub fn finish(self) -> TyMap {
-        Arc::new(self.map)
+    pub fn add_enum<T: Any>(&mut self, builder: EnumBuilder<T, M>) {
+        self.add_ty(builder.build());
+    }
+
+    pub fn with_enum<T: Any>(mut self, builder: EnumBuilder<T, M>) -> Self {
+        self.add_enum(builder);
+
+        self
+    }
+
+    pub fn add_module(&mut self, builder: ModuleBuilder<M>) {
+        self.map.extend(builder.map);
+        self.modules.push(builder.module);
+    }
+
+    pub fn with_module(mut self, builder: ModuleBuilder<M>) -> Self {
+        self.add_module(builder);
+
+        self
+    }
+
+    pub fn map(&self) -> &TyMapMut {
+        &self.map
+    }
+
+    pub fn set_coercion(&mut self, coercion: Coercion) {
+        self.coercion = coercion;
+    }
+
+    pub fn with_coercion(mut self, coercion: Coercion) -> Self {
+        self.set_coercion(coercion);
+
+        self
+    }
+
+    pub fn finish(self) -> Result<TyMap> {
+        self.finish_with_modules().map(|(map, _)| map)
+    }
+
+    pub fn finish_with_modules(mut self) -> Result<(TyMap, Module)> {
+        let ids: Vec<TypeId> = self.map.keys().cloned().collect();
+        let mut done = vec![];
+        for id in &ids {
+            resolve_parent(&mut self.map, *id, &mut done, &mut vec![])?;
+        }
+
+        if self.coercion != Coercion::default() {
+            for ty in self.map.values_mut() {
+                coerce_ty(self.coercion, ty);
+            }
+            for module in &mut self.modules {
+                coerce_module(self.coercion, module);
+            }
+        }
+        let mut qualified = vec![];
+        for module in &mut self.modules {
+            qualify_module(&mut self.map, &mut qualified, "", module);
+        }
+
+        for ty in self.map.values() {
+            overload::check(ty)?;
+        }
+        for module in &self.modules {
+            overload::check_module(module)?;
+        }
+
+        let mut nested = vec![];
+        for module in &self.modules {
+            collect_types(module, &mut nested);
+        }
+        let root = Module {
+            constants: vec![],
+            functions: vec![],
+            ident: Str::from(""),
+            modules: self.modules,
+            types: ids.into_iter().filter(|id| !nested.contains(id)).collect(),
+        };
+
+        Ok((Arc::new(self.map), root))
+    }
+}
+
+fn coerce_ty(coercion: Coercion, ty: &mut Ty) {
+    let ident = &ty.ident;
+    let operators = ty.operators.iter_mut().map(|&mut (_, ref mut func)| func);
+    let functions = ty.functions.iter_mut().chain(&mut ty.methods).chain(operators);
+    for func in functions {
+        let context = Str::from(format!("{}.{}", ident, func.ident));
+        coerce_function(coercion, context, func);
+    }
+
+    for variant in &mut ty.variants {
+        if let VariantKind::Constructor(ref mut func) = variant.kind {
+            let context = Str::from(format!("{}.{}", ident, variant.ident));
+            coerce_function(coercion, context, func);
+        }
+    }
+
+    for property in &mut ty.properties {
+        let context = Str::from(format!("{}.{}", ident, property.ident));
+        let args = vec![ValueTy::CustomMut, property.ty.clone()];
+        if let Some(ref mut set) = property.set {
+            *set = coerce_args(coercion, context, args, vec![], set.clone());
+        }
+    }
+}
+
+fn coerce_module(coercion: Coercion, module: &mut Module) {
+    for func in &mut module.functions {
+        let context = Str::from(format!("{}.{}", module.ident, func.ident));
+        coerce_function(coercion, context, func);
+    }
+
+    for module in &mut module.modules {
+        coerce_module(coercion, module);
+    }
+}
+
+fn coerce_function(coercion: Coercion, context: Str, func: &mut Function) {
+    let (args, names) = (func.args.clone(), func.arg_names.clone());
+    func.exec = coerce_args(coercion, context, args, names, func.exec.clone());
+}
+
+fn coerce_args(
+    coercion: Coercion,
+    context: Str,
+    args: Vec<ValueTy>,
+    names: Vec<Str>,
+    exec: FunPtr,
+) -> FunPtr {
+    let rest = match args.last() {
+        Some(&ValueTy::Rest(ref inner)) => Some((**inner).clone()),
+        _ => None,
+    };
+
+    Arc::new(move |val: Vec<Value>| {
+        let mut coerced = Vec::with_capacity(val.len());
+        for (index, value) in val.into_iter().enumerate() {
+            let value = match args.get(index).or(rest.as_ref()) {
+                Some(ty) => coercion.coerce(value, ty).map_err(|e| Error::Argument {
+                    index,
+                    name: names.get(index).cloned(),
+                    error: Box::new(e),
+                }),
+                None => Ok(value),
+            };
+            match value {
+                Ok(value) => coerced.push(value),
+                Err(e) => return Value::Error(call_error(&context, e)),
+            }
+        }
+
+        exec(coerced)
+    })
+}
+
+fn qualify_module(
+    map: &mut TyMapMut,
+    qualified: &mut Vec<TypeId>,
+    parent: &str,
+    module: &mut Module,
+) {
+    let path = match parent {
+        "" => module.ident.to_string(),
+        parent => format!("{}.{}", parent, module.ident),
+    };
+
+    if !parent.is_empty() {
+        for func in &mut module.functions {
+            qualify(parent, &mut func.exec);
+        }
+    }
+    // Types are shared between modules and the flat map, so each one keeps the first path.
+    for id in &module.types {
+        if qualified.contains(id) {
+            continue;
+        }
+        if let Some(ty) = map.get_mut(id) {
+            qualify_ty(&path, ty);
+            qualified.push(*id);
+        }
+    }
+    for module in &mut module.modules {
+        qualify_module(map, qualified, &path, module);
+    }
+}
+
+fn qualify_ty(path: &str, ty: &mut Ty) {
+    let operators = ty.operators.iter_mut().map(|&mut (_, ref mut func)| func);
+    for func in ty.functions.iter_mut().chain(&mut ty.methods).chain(operators) {
+        qualify(path, &mut func.exec);
+    }
+
+    for variant in &mut ty.variants {
+        if let VariantKind::Constructor(ref mut func) = variant.kind {
+            qualify(path, &mut func.exec);
+        }
+    }
+
+    for property in &mut ty.properties {
+        for fptr in property.get.iter_mut().chain(&mut property.set) {
+            qualify(path, fptr);
+        }
+    }
+}
+
+fn qualify(prefix: &str, exec: &mut FunPtr) {
+    let (prefix, inner) = (prefix.to_owned(), exec.clone());
+    *exec = Arc::new(move |val: Vec<Value>| match inner(val) {
+        Value::Error(Error::Call { ident, error }) => Value::Error(Error::Call {
+            ident: Str::from(format!("{}.{}", prefix, ident)),
+            error,
+        }),
+        other => other,
+    });
+}
+
+fn collect_types(module: &Module, types: &mut Vec<TypeId>) {
+    types.extend(module.types.iter().cloned());
+    for module in &module.modules {
+        collect_types(module, types);
+    }
+}
+
+impl Builder<Local> {
+    pub fn map_mut(&mut self) -> &mut TyMapMut {
+        &mut self.map
+    }
+}
+
+impl Builder<ThreadSafe> {
+    pub fn finish_sync(self) -> Result<SyncTyMap> {
+        self.finish().map(|map| unsafe { SyncTyMap::new(map) })
+    }
+}
+
+impl<M> Default for Builder<M> {
+    fn default() -> Self {
+        Builder {
+            coercion: Coercion::default(),
+            map: TyMapMut::default(),
+            marker: PhantomData,
+            modules: vec![],
+        }
+    }
+}
+
+#[must_use]
+pub struct ModuleBuilder<M = Local> {
+    map: TyMapMut,
+    marker: PhantomData<M>,
+    module: Module,
+}
+
+impl<M> ModuleBuilder<M> {
+    pub fn new(ident: &'static str) -> Self {
+        ModuleBuilder {
+            map: TyMapMut::default(),
+            marker: PhantomData,
+            module: Module {
+                constants: vec![],
+                functions: vec![],
+                ident: Str::from(ident),
+                modules: vec![],
+                types: vec![],
+            },
+        }
+    }
+
+    pub fn add_function<C, F, V>(&mut self, ident: &'static str, f: C)
+    where
+        C: Fn(F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        let context = Str::from(format!("{}.{}", self.module.ident, ident));
+        self.module.functions.push(function(ident, context, true, f));
+    }
+
+    pub fn with_function<C, F, V>(mut self, ident: &'static str, f: C) -> Self
+    where
+        C: Fn(F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_function(ident, f);
+
+        self
+    }
+
+    pub fn add_constant<V>(&mut self, ident: &'static str, value: V)
+    where
+        V: IntoValue + Clone + Captures<M> + 'static,
+    {
+        let producer = move || IntoValue::into(value.clone()).into();
+
+        self.module.constants.push(Constant {
+            ident: Str::from(ident),
+            ty: V::in_ty(),
+            value: Arc::new(producer),
+        });
+    }
+
+    pub fn with_constant<V>(mut self, ident: &'static str, value: V) -> Self
+    where
+        V: IntoValue + Clone + Captures<M> + 'static,
+    {
+        self.add_constant(ident, value);
+
+        self
+    }
+
+    pub fn add_ty<T: Any>(&mut self, builder: TyBuilder<T, M>) {
+        self.map.insert(TypeId::of::<T>(), builder.ty);
+        self.module.types.push(TypeId::of::<T>());
+    }
+
+    pub fn with_ty<T: Any>(mut self, builder: TyBuilder<T, M>) -> Self {
+        self.add_ty(builder);
+
+        self
+    }
+
+    pub fn add_module(&mut self, builder: ModuleBuilder<M>) {
+        self.map.extend(builder.map);
+        self.module.modules.push(builder.module);
+    }
+
+    pub fn with_module(mut self, builder: ModuleBuilder<M>) -> Self {
+        self.add_module(builder);
+
+        self
+    }
+}
+
+fn resolve_parent(
+    map: &mut TyMapMut,
+    id: TypeId,
+    done: &mut Vec<TypeId>,
+    stack: &mut Vec<TypeId>,
+) -> Result<()> {
+    if done.contains(&id) {
+        return Ok(());
+    }
+
+    let parent = match map[&id].parent {
+        Some(ref parent) => parent.id,
+        None => {
+            done.push(id);
+
+            return Ok(());
+        }
+    };
+    if stack.contains(&id) {
+        return Err(Error::ParentCycle {
+            ident: map[&id].ident.clone(),
+        });
+    }
+    if !map.contains_key(&parent) {
+        return Err(Error::MissingParent {
+            ident: map[&id].ident.clone(),
+        });
+    }
+
+    stack.push(id);
+    resolve_parent(map, parent, done, stack)?;
+    stack.pop();
+
+    let parent = map[&parent].clone();
+    inherit(map.get_mut(&id).unwrap(), &parent);
+    done.push(id);
+
+    Ok(())
+}
+
+fn inherit(ty: &mut Ty, parent_ty: &Ty) {
+    let mut parent = ty.parent.take().unwrap();
+    let own_methods: Vec<Str> = ty.methods.iter().map(|m| m.ident.clone()).collect();
+    let own_properties: Vec<Str> = ty.properties.iter().map(|p| p.ident.clone()).collect();
+
+    for method in &parent_ty.methods {
+        if own_methods.contains(&method.ident) {
+            continue;
+        }
+
+        let mutable = method.args.first() == Some(&ValueTy::CustomMut);
+        let context = Str::from(format!("{}.{}", ty.ident, method.ident));
+        let mut method = method.clone();
+        method.exec = delegate(method.exec, mutable, context, &parent);
+
+        if !parent.inherited.contains(&method.ident) {
+            parent.inherited.push(method.ident.clone());
+        }
+        ty.methods.push(method);
+    }
+
+    let own_operators: Vec<Operator> = ty.operators.iter().map(|&(op, _)| op).collect();
+    for &(op, ref operator) in &parent_ty.operators {
+        if own_operators.contains(&op) {
+            continue;
+        }
+
+        let mutable = operator.args.first() == Some(&ValueTy::CustomMut);
+        let context = Str::from(format!("{}.{}", ty.ident, op.ident()));
+        let mut operator = operator.clone();
+        operator.exec = delegate(operator.exec, mutable, context, &parent);
+
+        ty.operators.push((op, operator));
+    }
+
+    for property in &parent_ty.properties {
+        if own_properties.contains(&property.ident) {
+            continue;
+        }
+
+        let context = Str::from(format!("{}.{}", ty.ident, property.ident));
+        let mut property = property.clone();
+        property.get = property
+            .get
+            .map(|get| delegate(get, false, context.clone(), &parent));
+        property.set = property
+            .set
+            .map(|set| delegate(set, true, context, &parent));
+
+        if !parent.inherited.contains(&property.ident) {
+            parent.inherited.push(property.ident.clone());
+        }
+        ty.properties.push(property);
+    }
+
+    parent.ident = parent_ty.ident.clone();
+    ty.parent = Some(parent);
+}
+
+fn delegate(exec: FunPtr, mutable: bool, context: Str, parent: &Parent) -> FunPtr {
+    let project = parent.project.clone();
+    let project_mut = parent.project_mut.clone();
+
+    Arc::new(move |val: Vec<Value>| {
+        let mut val: Vec<Value> = val
+            .into_iter()
+            .map(|arg| match arg {
+                Value::CustomRef(any) => Value::CustomRef(project(any)),
+                Value::CustomMut(any) => Value::CustomMut(project_mut(any)),
+                other => other,
+            })
+            .collect();
+        if val.is_empty() {
+            return exec(val);
+        }
+
+        match val.remove(0) {
+            Value::Shared(s) => {
+                let res = if mutable {
+                    s.with_mut(|any| {
+                        let mut args = vec![Value::CustomMut(project_mut(any))];
+                        args.extend(val);
+
+                        exec(args)
+                    })
+                } else {
+                    s.with_ref(|any| {
+                        let mut args = vec![Value::CustomRef(project(any))];
+                        args.extend(val);
+
+                        exec(args)
+                    })
+                };
+
+                res.map_err(|e| call_error(&context, self_error(e))).into()
+            }
+            this => {
+                val.insert(0, this);
+
+                exec(val)
+            }
+        }
+    })
+}
+
+fn project<T, U>(this: &Any) -> &Any
+where
+    T: DerefMut<Target = U> + Any,
+    U: Any,
+{
+    match this.downcast_ref::<T>() {
+        Some(this) => &**this,
+        None => this,
+    }
+}
+
+fn project_mut<T, U>(this: &mut Any) -> &mut Any
+where
+    T: DerefMut<Target = U> + Any,
+    U: Any,
+{
+    if this.is::<T>() {
+        &mut **this.downcast_mut::<T>().unwrap()
+    } else {
+        this
+    }
+}
+
+impl Function {
+    pub fn new<C, F, V>(ident: &'static str, f: C) -> Self
+    where
+        C: Fn(F) -> V + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        function(ident, Str::from(ident), true, f)
+    }
+}
+
+fn function<C, F, V>(ident: &'static str, context: Str, catch_unwind: bool, f: C) -> Function
+where
+    C: Fn(F) -> V + 'static,
+    F: for<'a> FromMultiValue<'a>,
+    V: IntoValue,
+{
+    let fptr = move |val: Vec<Value>| {
+        let args = F::from(val.into())?;
+        let res = f(args);
+
+        V::into(res)
+    };
+    let fptr = move |val: Vec<Value>| {
+        guard(catch_unwind, || fptr(val))
+            .map_err(|e| call_error(&context, e))
+            .into()
+    };
+
+    Function {
+        exec: Arc::new(fptr),
+        ident: Str::from(ident),
+        args: F::multi_ty(),
+        arg_names: vec![],
+        defaults: vec![],
+        ret: V::in_ty(),
+    }
+}
+
+#[must_use]
+pub struct TyBuilder<T, M = Local> {
+    catch_unwind: bool,
+    last: Option<Registered>,
+    marker: PhantomData<(T, M)>,
+    ty: Ty,
+}
+
+pub trait Fields<M>: Any + Sized {
+    fn ident() -> &'static str;
+
+    fn add_fields(builder: &mut TyBuilder<Self, M>);
+}
+
+pub trait Items<M>: Any + Sized {
+    fn add_items(builder: &mut TyBuilder<Self, M>);
+}
+
+#[derive(Clone, Copy)]
+enum Registered {
+    Function,
+    Method,
+}
+
+impl<T, M> TyBuilder<T, M>
+where
+    T: Any + 'static,
+{
+    pub fn new(ident: &'static str) -> Self {
+        TyBuilder {
+            catch_unwind: true,
+            last: None,
+            marker: PhantomData,
+            ty: Ty {
+                clone: None,
+                constants: vec![],
+                functions: vec![],
+                id: TypeId::of::<T>(),
+                ident: Str::from(ident),
+                interfaces: vec![],
+                methods: vec![],
+                operators: vec![],
+                parent: None,
+                properties: vec![],
+                variants: vec![],
+            },
+        }
+    }
+
+    pub fn derived() -> Self
+    where
+        T: Fields<M>,
+    {
+        let mut builder = TyBuilder::new(T::ident());
+        T::add_fields(&mut builder);
+
+        builder
+    }
+
+    pub fn add_items(&mut self)
+    where
+        T: Items<M>,
+    {
+        T::add_items(self);
+    }
+
+    pub fn with_items(mut self) -> Self
+    where
+        T: Items<M>,
+    {
+        self.add_items();
+
+        self
+    }
+
+    pub fn ty(&self) -> &Ty {
+        &self.ty
+    }
+
+    pub fn catch_unwind(&self) -> bool {
+        self.catch_unwind
+    }
+
+    pub fn add_function<C, F, V>(&mut self, ident: &'static str, f: C)
+        where
+            C: Fn(F) -> V + Captures<M> + 'static,
+            F: for<'a> FromMultiValue<'a>,
+            V: IntoValue,
+    {
+        self.push_function(ident, f);
+    }
+
+    pub fn with_function<C, F, V>(mut self, ident: &'static str, f: C) -> Self
+    where
+        C: Fn(F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_function(ident, f);
+
+        self
+    }
+
+    pub fn add_method<C, F, V>(&mut self, ident: &'static str, f: C)
+        where
+            C: Fn(&T, F) -> V + Captures<M> + 'static,
+            F: for<'a> FromMultiValue<'a>,
+            V: IntoValue,
+    {
+        self.push_method(ident, f);
+    }
+
+    pub fn with_method<C, F, V>(mut self, ident: &'static str, f: C) -> Self
+    where
+        C: Fn(&T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_method(ident, f);
+
+        self
+    }
+
+    pub fn add_method_mut<C, F, V>(&mut self, ident: &'static str, f: C)
+        where
+            C: Fn(&mut T, F) -> V + Captures<M> + 'static,
+            F: for<'a> FromMultiValue<'a>,
+            V: IntoValue,
+    {
+        let method = self.method_mut(ident, f);
+        self.ty.methods.push(method);
+        self.last = Some(Registered::Method);
+    }
+
+    pub fn with_method_mut<C, F, V>(mut self, ident: &'static str, f: C) -> Self
+    where
+        C: Fn(&mut T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_method_mut(ident, f);
+
+        self
+    }
+
+    pub fn add_constant<V>(&mut self, ident: &'static str, value: V)
+    where
+        V: IntoValue + Clone + Captures<M> + 'static,
+    {
+        let producer = move || IntoValue::into(value.clone()).into();
+
+        self.ty.constants.push(Constant {
+            ident: Str::from(ident),
+            ty: V::in_ty(),
+            value: Arc::new(producer),
+        });
+    }
+
+    pub fn with_constant<V>(mut self, ident: &'static str, value: V) -> Self
+    where
+        V: IntoValue + Clone + Captures<M> + 'static,
+    {
+        self.add_constant(ident, value);
+
+        self
+    }
+
+    pub fn add_operator<C, F, V>(&mut self, op: Operator, f: C)
+    where
+        C: Fn(&T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        let operator = self.method(op.ident(), f);
+        self.ty.operators.push((op, operator));
+        self.last = None;
+    }
+
+    pub fn with_operator<C, F, V>(mut self, op: Operator, f: C) -> Self
+    where
+        C: Fn(&T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_operator(op, f);
+
+        self
+    }
+
+    pub fn add_operator_mut<C, F, V>(&mut self, op: Operator, f: C)
+    where
+        C: Fn(&mut T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        let operator = self.method_mut(op.ident(), f);
+        self.ty.operators.push((op, operator));
+        self.last = None;
+    }
+
+    pub fn with_operator_mut<C, F, V>(mut self, op: Operator, f: C) -> Self
+    where
+        C: Fn(&mut T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_operator_mut(op, f);
+
+        self
+    }
+
+    pub fn add_defaults<D>(&mut self, defaults: D)
+    where
+        D: IntoMultiValue + Clone + Captures<M> + 'static,
+    {
+        let ty_ident = self.ty.ident.clone();
+        let (func, this) = self.last_mut();
+        let count = D::multi_in_ty().len();
+        assert!(
+            count <= func.args.len() - this,
+            "More defaults than parameters for {}",
+            func.ident
+        );
+
+        let producers: Vec<DefaultPtr> = (0..count)
+            .map(|i| {
+                let defaults = defaults.clone();
+                let producer = move || {
+                    defaults
+                        .clone()
+                        .into_multi()
+                        .map(|mut v| v.swap_remove(i))
+                        .into()
+                };
+
+                Arc::new(producer) as DefaultPtr
+            })
+            .collect();
+
+        let exec = func.exec.clone();
+        let context = Str::from(format!("{}.{}", ty_ident, func.ident));
+        let total = func.args.len();
+        let fill = producers.clone();
+        func.exec = Arc::new(move |mut val: Vec<Value>| {
+            let missing = total.saturating_sub(val.len());
+            if missing > fill.len() {
+                let error = Error::WrongArgsNumber {
+                    expected: (total - fill.len()) as u16,
+                    found: val.len() as u16,
+                };
+
+                return Value::Error(call_error(&context, error));
+            }
+            val.extend(fill[fill.len() - missing..].iter().map(|d| d()));
+
+            exec(val)
+        });
+        func.defaults = producers;
+    }
+
+    pub fn with_defaults<D>(mut self, defaults: D) -> Self
+    where
+        D: IntoMultiValue + Clone + Captures<M> + 'static,
+    {
+        self.add_defaults(defaults);
+
+        self
+    }
+
+    pub fn add_function_named<C, F, V>(&mut self, ident: &'static str, names: &[&'static str], f: C)
+    where
+        C: Fn(F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_function(ident, f);
+        self.name_last(names);
+    }
+
+    pub fn with_function_named<C, F, V>(
+        mut self,
+        ident: &'static str,
+        names: &[&'static str],
+        f: C,
+    ) -> Self
+    where
+        C: Fn(F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_function_named(ident, names, f);
+
+        self
+    }
+
+    pub fn add_method_named<C, F, V>(&mut self, ident: &'static str, names: &[&'static str], f: C)
+    where
+        C: Fn(&T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_method(ident, f);
+        self.name_last(names);
+    }
+
+    pub fn with_method_named<C, F, V>(
+        mut self,
+        ident: &'static str,
+        names: &[&'static str],
+        f: C,
+    ) -> Self
+    where
+        C: Fn(&T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_method_named(ident, names, f);
+
+        self
+    }
+
+    pub fn add_method_mut_named<C, F, V>(
+        &mut self,
+        ident: &'static str,
+        names: &[&'static str],
+        f: C,
+    ) where
+        C: Fn(&mut T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_method_mut(ident, f);
+        self.name_last(names);
+    }
+
+    pub fn with_method_mut_named<C, F, V>(
+        mut self,
+        ident: &'static str,
+        names: &[&'static str],
+        f: C,
+    ) -> Self
+    where
+        C: Fn(&mut T, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        self.add_method_mut_named(ident, names, f);
+
+        self
+    }
+
+    pub fn add_interface<I>(&mut self, interface: &InterfaceBuilder<I, M>)
+    where
+        I: Interface + ?Sized,
+        T: Implements<I>,
+    {
+        self.ty.interfaces.push(interface.ident.clone());
+        self.ty.methods.extend(interface.methods.iter().cloned());
+        self.last = None;
+    }
+
+    pub fn with_interface<I>(mut self, interface: &InterfaceBuilder<I, M>) -> Self
+    where
+        I: Interface + ?Sized,
+        T: Implements<I>,
+    {
+        self.add_interface(interface);
+
+        self
+    }
+
+    pub fn add_parent<U>(&mut self)
+    where
+        T: DerefMut<Target = U>,
+        U: Any,
+    {
+        self.ty.parent = Some(Parent {
+            id: TypeId::of::<U>(),
+            ident: Str::from(type_name_of::<U>()),
+            inherited: vec![],
+            project: Arc::new(project::<T, U>),
+            project_mut: Arc::new(project_mut::<T, U>),
+        });
+    }
+
+    pub fn with_parent<U>(mut self) -> Self
+    where
+        T: DerefMut<Target = U>,
+        U: Any,
+    {
+        self.add_parent();
+
+        self
+    }
+
+    pub fn add_clone(&mut self)
+    where
+        T: Clone,
+    {
+        self.ty.clone = Some(Arc::new(|this: &Any| {
+            let this: &T = this.downcast_ref().expect("Clone hook called with wrong type");
+
+            Box::new(this.clone()) as Box<Any>
+        }));
+    }
+
+    pub fn with_clone(mut self) -> Self
+    where
+        T: Clone,
+    {
+        self.add_clone();
+
+        self
+    }
+
+    pub fn set_catch_unwind(&mut self, catch_unwind: bool) {
+        self.catch_unwind = catch_unwind;
+    }
+
+    pub fn with_catch_unwind(mut self, catch_unwind: bool) -> Self {
+        self.set_catch_unwind(catch_unwind);
+
+        self
+    }
+
+    pub fn add_property<P>(&mut self, builder: PropertyBuilder<T, P, M>) {
+        let mut property = builder.property;
+        let context = self.context(&property.ident);
+        let catch_unwind = self.catch_unwind;
+        property.get = property
+            .get
+            .map(|get| with_context(context.clone(), catch_unwind, get));
+        property.set = property
+            .set
+            .map(|set| with_context(context, catch_unwind, set));
+
+        self.ty.properties.push(property);
+    }
+
+    pub fn with_property<P>(mut self, builder: PropertyBuilder<T, P, M>) -> Self {
+        self.add_property(builder);
+
+        self
+    }
+
+    fn push_function<C, F, V>(&mut self, ident: &'static str, f: C)
+    where
+        C: Fn(F) -> V + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        let context = self.context(ident);
+        let catch_unwind = self.catch_unwind;
+        self.ty.functions.push(function(ident, context, catch_unwind, f));
+        self.last = Some(Registered::Function);
+    }
+
+    fn push_method<C, F, V>(&mut self, ident: &'static str, f: C)
+    where
+        C: Fn(&T, F) -> V + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        let method = self.method(ident, f);
+        self.ty.methods.push(method);
+        self.last = Some(Registered::Method);
+    }
+
+    fn method<C, F, V>(&self, ident: &'static str, f: C) -> Function
+    where
+        C: Fn(&T, F) -> V + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        let fptr = move |val: Vec<Value>| {
+            let mut deque: MultiVal = val.into();
+            let this = deque.pop_front().ok_or(Error::MissingSelfArg)?;
+
+            let args = F::from(deque).map_err(|e| shift_args(e, 1))?;
+
+            with_this(this, |this: &T| V::into(f(this, args)))
+        };
+        let mut args = F::multi_ty();
+        args.insert(0, <&T as FromValue>::out_ty());
+
+        let context = self.context(ident);
+        let catch_unwind = self.catch_unwind;
+        let fptr = move |val: Vec<Value>| {
+            guard(catch_unwind, || fptr(val))
+                .map_err(|e| call_error(&context, e))
+                .into()
+        };
+        Function {
+            exec: Arc::new(fptr),
+            ident: Str::from(ident),
+            args,
+            arg_names: vec![],
+            defaults: vec![],
+            ret: V::in_ty(),
+        }
+    }
+
+    fn method_mut<C, F, V>(&self, ident: &'static str, f: C) -> Function
+    where
+        C: Fn(&mut T, F) -> V + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
+    {
+        let fptr = move |val: Vec<Value>| {
+            let mut deque: MultiVal = val.into();
+            let this = deque.pop_front().ok_or(Error::MissingSelfArg)?;
+
+            let args = F::from(deque).map_err(|e| shift_args(e, 1))?;
+
+            with_this_mut(this, |this: &mut T| V::into(f(this, args)))
+        };
+        let context = self.context(ident);
+        let catch_unwind = self.catch_unwind;
+        let fptr = move |val: Vec<Value>| {
+            guard(catch_unwind, || fptr(val))
+                .map_err(|e| call_error(&context, e))
+                .into()
+        };
+        let mut args = F::multi_ty();
+        args.insert(0, <&mut T as FromValue>::out_ty());
+        Function {
+            exec: Arc::new(fptr),
+            ident: Str::from(ident),
+            args,
+            arg_names: vec![],
+            defaults: vec![],
+            ret: V::in_ty(),
+        }
+    }
+
+    fn last_mut(&mut self) -> (&mut Function, usize) {
+        match self.last {
+            Some(Registered::Function) => (self.ty.functions.last_mut().unwrap(), 0),
+            Some(Registered::Method) => (self.ty.methods.last_mut().unwrap(), 1),
+            None => panic!("No function or method has been registered yet"),
+        }
+    }
+
+    fn name_last(&mut self, names: &[&'static str]) {
+        let (func, this) = self.last_mut();
+        assert_eq!(
+            names.len(),
+            func.args.len() - this,
+            "Wrong number of argument names for {}",
+            func.ident
+        );
+
+        let mut arg_names = vec![];
+        if this == 1 {
+            arg_names.push(Str::from("self"));
+        }
+        arg_names.extend(names.iter().map(|&name| Str::from(name)));
+
+        let exec = func.exec.clone();
+        let names = arg_names.clone();
+        func.exec = Arc::new(move |val: Vec<Value>| match exec(val) {
+            Value::Error(e) => Value::Error(name_args(e, &names)),
+            other => other,
+        });
+        func.arg_names = arg_names;
+    }
+
+    fn context(&self, ident: &str) -> Str {
+        Str::from(format!("{}.{}", self.ty.ident, ident))
+    }
+}
+
+impl<T> TyBuilder<T, Local>
+where
+    T: Any + 'static,
+{
+    pub fn ty_mut(&mut self) -> &mut Ty {
+        &mut self.ty
+    }
+}
+
+#[must_use]
+pub struct PropertyBuilder<T, P, M = Local> {
+    property: Property,
+    marker: PhantomData<(T, P, M)>,
+}
+
+impl<T, P, M> PropertyBuilder<T, P, M>
+where
+    T: Any,
+    P: for<'a> FromValue<'a> + IntoValue,
+{
+    pub fn new(ident: &'static str) -> Self {
+        PropertyBuilder {
+            property: Property {
+                ident: Str::from(ident),
+                ty: P::out_ty(),
+                get: None,
+                set: None,
+            },
+            marker: PhantomData,
+        }
+    }
+
+    pub fn add_getter<G>(&mut self, get: G)
+    where
+        G: Fn(&T) -> P + Captures<M> + 'static,
+    {
+        let fptr = move |val: Vec<Value>| {
+            let mut deque: MultiVal = val.into();
+            let this = deque.pop_front().ok_or(Error::MissingSelfArg)?;
+
+            with_this(this, |this: &T| P::into(get(this)))
+        };
+        let fptr = move |val: Vec<Value>| fptr(val).into();
+
+        self.property.get = Some(Arc::new(fptr));
+    }
+
+    pub fn with_getter<G>(mut self, get: G) -> Self
+        where
+            G: Fn(&T) -> P + Captures<M> + 'static,
+    {
+        self.add_getter(get);
+
+        self
+    }
+
+    pub fn add_setter<S>(&mut self, set: S)
+        where
+            S: Fn(&mut T, P) -> () + Captures<M> + 'static,
+    {
+        let fptr = move |val: Vec<Value>| {
+            let mut deque: MultiVal = val.into();
+            let this = deque.pop_front().ok_or(Error::MissingSelfArg)?;
+
+            let value = deque.pop_front().ok_or(Error::WrongArgsNumber {
+                expected: 2,
+                found: 1,
+            })?;
+            let value = P::from(value).map_err(|e| Error::Argument {
+                index: 1,
+                name: None,
+                error: Box::new(e),
+            })?;
+
+            with_this_mut(this, |this: &mut T| Ok(set(this, value)))
+        };
+        let fptr = move |val: Vec<Value>| fptr(val).map(|_| Value::Void).into();
+
+        self.property.set = Some(Arc::new(fptr));
+    }
+
+    pub fn with_setter<S>(mut self, set: S) -> Self
+    where
+        S: Fn(&mut T, P) -> () + Captures<M> + 'static,
+    {
+        self.add_setter(set);
+
+        self
+    }
+}
+
+#[must_use]
+pub struct EnumBuilder<T, M = Local> {
+    next: i64,
+    ty: TyBuilder<T, M>,
+    units: Vec<Unit<T>>,
+    variant_of: Option<Arc<Fn(&T) -> &'static str>>,
+}
+
+struct Unit<T> {
+    discriminant: i64,
+    id: Discriminant<T>,
+    ident: Str,
+    make: Arc<Fn() -> T>,
+}
+
+impl<T, M> EnumBuilder<T, M>
+where
+    T: Any,
+{
+    pub fn new(ident: &'static str) -> Self {
+        EnumBuilder::from_ty(TyBuilder::new(ident))
+    }
+
+    pub fn from_ty(ty: TyBuilder<T, M>) -> Self {
+        EnumBuilder {
+            next: 0,
+            ty,
+            units: vec![],
+            variant_of: None,
+        }
+    }
+
+    pub fn add_unit(&mut self, ident: &'static str, value: T)
+    where
+        T: Clone + Captures<M>,
+    {
+        let discriminant = self.next;
+        let make = Arc::new(move || value.clone());
+        let producer = {
+            let make = make.clone();
+            move || IntoValue::into(Box::new(make())).into()
+        };
+
+        self.next += 1;
+        self.units.push(Unit {
+            discriminant,
+            id: mem::discriminant(&make()),
+            ident: Str::from(ident),
+            make,
+        });
+        self.ty.ty.variants.push(Variant {
+            discriminant: Some(discriminant),
+            ident: Str::from(ident),
+            kind: VariantKind::Unit(Arc::new(producer)),
+        });
+    }
+
+    pub fn with_unit(mut self, ident: &'static str, value: T) -> Self
+    where
+        T: Clone + Captures<M>,
+    {
+        self.add_unit(ident, value);
+
+        self
+    }
+
+    pub fn set_discriminant(&mut self, discriminant: i64) {
+        let variant = self
+            .ty
+            .ty
+            .variants
+            .last_mut()
+            .expect("No variant has been registered yet");
+        assert!(
+            variant.discriminant.is_some(),
+            "Only unit variants have a discriminant, {} is a constructor",
+            variant.ident
+        );
+
+        variant.discriminant = Some(discriminant);
+        self.units.last_mut().unwrap().discriminant = discriminant;
+        self.next = discriminant + 1;
+    }
+
+    pub fn with_discriminant(mut self, discriminant: i64) -> Self {
+        self.set_discriminant(discriminant);
+
+        self
+    }
+
+    pub fn add_variant<C, F>(&mut self, ident: &'static str, f: C)
+    where
+        C: Fn(F) -> T + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+    {
+        let context = self.ty.context(ident);
+        let catch_unwind = self.ty.catch_unwind;
+        let constructor = function(ident, context, catch_unwind, move |args| Box::new(f(args)));
+
+        self.next += 1;
+        self.ty.ty.variants.push(Variant {
+            discriminant: None,
+            ident: Str::from(ident),
+            kind: VariantKind::Constructor(constructor),
+        });
+    }
+
+    pub fn with_variant<C, F>(mut self, ident: &'static str, f: C) -> Self
+    where
+        C: Fn(F) -> T + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+    {
+        self.add_variant(ident, f);
+
+        self
+    }
+
+    pub fn add_variant_of<G>(&mut self, f: G)
+    where
+        G: Fn(&T) -> &'static str + Captures<M> + 'static,
+    {
+        self.variant_of = Some(Arc::new(f));
+    }
+
+    pub fn with_variant_of<G>(mut self, f: G) -> Self
+    where
+        G: Fn(&T) -> &'static str + Captures<M> + 'static,
+    {
+        self.add_variant_of(f);
+
+        self
+    }
+
+    fn build(self) -> TyBuilder<T, M> {
+        let EnumBuilder {
+            mut ty,
+            units,
+            variant_of,
+            ..
+        } = self;
+        let c_like = ty.ty.variants.iter().all(|v| v.discriminant.is_some());
+        assert!(
+            c_like || variant_of.is_some(),
+            "{} has constructor variants, but no variant_of to name them",
+            ty.ty.ident
+        );
+
+        let units = Arc::new(units);
+        let find = {
+            let units = units.clone();
+            move |this: &T| {
+                let id = mem::discriminant(this);
+
+                units
+                    .iter()
+                    .find(|unit| unit.id == id)
+                    .map(|unit| (unit.ident.clone(), unit.discriminant))
+            }
+        };
+
+        // `push_*` have no `Captures<M>` bound; these closures only capture the units
+        // and `variant_of`, which were checked against `M` by `add_unit` and
+        // `add_variant_of`.
+        ty.push_method("variant", {
+            let find = find.clone();
+            move |this: &T, ()| match variant_of {
+                Some(ref variant_of) => Some(Str::from(variant_of(this))),
+                None => find(this).map(|(ident, _)| ident),
+            }
+        });
+
+        if c_like && !units.is_empty() {
+            ty.push_method("to_int", move |this: &T, ()| find(this).map(|(_, d)| d));
+            ty.push_function("from_int", {
+                let units = units.clone();
+                move |(discriminant,): (i64,)| {
+                    units
+                        .iter()
+                        .find(|unit| unit.discriminant == discriminant)
+                        .map(|unit| Box::new((unit.make)()))
+                }
+            });
+            ty.push_function("from_name", move |(ident,): (String,)| {
+                units
+                    .iter()
+                    .find(|unit| unit.ident == ident)
+                    .map(|unit| Box::new((unit.make)()))
+            });
+        }
+        ty.last = None;
+
+        ty
     }
 }
 
 #[must_use]
-pub struct TyBuilder<T> {
-    pub marker: PhantomData<T>,
-    pub ty: Ty,
+pub struct InterfaceBuilder<I: ?Sized, M = Local> {
+    catch_unwind: bool,
+    ident: Str,
+    marker: PhantomData<(M, Box<I>)>,
+    methods: Vec<Function>,
 }
 
-impl<T> TyBuilder<T>
+impl<I, M> InterfaceBuilder<I, M>
 where
-    T: Any + 'static,
+    I: Interface + ?Sized + 'static,
 {
     pub fn new(ident: &'static str) -> Self {
-        TyBuilder {
+        InterfaceBuilder {
+            catch_unwind: true,
+            ident: Str::from(ident),
             marker: PhantomData,
-            ty: Ty {
-                functions: vec![],
-                id: TypeId::of::<T>(),
-                ident: ident.into(),
-                methods: vec![],
-                properties: vec![],
-            },
+            methods: vec![],
         }
     }
 
-    pub fn add_function<C, F, V>(&mut self, ident: &'static str, f: C)
-        where
-            C: Fn(F) -> V + 'static,
-            F: for<'a> FromMultiValue<'a>,
-            V: IntoValue,
-    {
-        let fptr = move |val: Vec<Value>| {
-            let args = F::from(val.into())?;
-            let res = f(args);
-
-            V::into(res)
-        };
-        let fptr = move |val: Vec<Value>| fptr(val).into();
-        self.ty.functions.push(Function {
-            exec: Arc::new(fptr),
-            ident: ident.into(),
-            args: F::multi_ty(),
-            ret: V::in_ty(),
-        });
-    }
-
-    pub fn with_function<C, F, V>(mut self, ident: &'static str, f: C) -> Self
+    pub fn add_method<C, F, V>(&mut self, ident: &'static str, f: C)
     where
-        C: Fn(F) -> V + 'static,
+        C: Fn(&I, F) -> V + Captures<M> + 'static,
         F: for<'a> FromMultiValue<'a>,
         V: IntoValue,
-    {
-        self.add_function(ident, f);
-
-        self
-    }
-
-    pub fn add_method<C, F, V>(&mut self, ident: &'static str, f: C)
-        where
-            C: Fn(&T, F) -> V + 'static,
-            F: for<'a> FromMultiValue<'a>,
-            V: IntoValue,
     {
         let fptr = move |val: Vec<Value>| {
             let mut deque: MultiVal = val.into();
-            let this = <&T as FromValue>::from(deque.pop_front().ok_or(Error::MissingSelfArg)?)?;
+            let this = deque.pop_front().ok_or(Error::MissingSelfArg)?;
 
-            let args = F::from(deque)?;
-            let res = f(this, args);
+            let args = F::from(deque).map_err(|e| shift_args(e, 1))?;
 
-            V::into(res)
+            with_dyn(this, |this: &I| V::into(f(this, args)))
         };
         let mut args = F::multi_ty();
-        args.insert(0, <&T as FromValue>::out_ty());
+        args.insert(0, ValueTy::CustomRef);
 
-        let fptr = move |val: Vec<Value>| fptr(val).into();
-        self.ty.methods.push(Function {
-            exec: Arc::new(fptr),
-            ident: ident.into(),
-            args,
-            ret: V::in_ty(),
-        });
+        self.push(ident, args, V::in_ty(), fptr);
     }
 
     pub fn with_method<C, F, V>(mut self, ident: &'static str, f: C) -> Self
     where
-        C: Fn(&T, F) -> V + 'static,
+        C: Fn(&I, F) -> V + Captures<M> + 'static,
         F: for<'a> FromMultiValue<'a>,
         V: IntoValue,
     {
@@ -121,35 +1484,28 @@ where
     }
 
     pub fn add_method_mut<C, F, V>(&mut self, ident: &'static str, f: C)
-        where
-            C: Fn(&mut T, F) -> V + 'static,
-            F: for<'a> FromMultiValue<'a>,
-            V: IntoValue,
+    where
+        C: Fn(&mut I, F) -> V + Captures<M> + 'static,
+        F: for<'a> FromMultiValue<'a>,
+        V: IntoValue,
     {
         let fptr = move |val: Vec<Value>| {
             let mut deque: MultiVal = val.into();
-            let this =
-                <&mut T as FromValue>::from(deque.pop_front().ok_or(Error::MissingSelfArg)?)?;
+            let this = deque.pop_front().ok_or(Error::MissingSelfArg)?;
 
-            let args = F::from(deque)?;
-            let res = f(this, args);
+            let args = F::from(deque).map_err(|e| shift_args(e, 1))?;
 
-            V::into(res)
+            with_dyn_mut(this, |this: &mut I| V::into(f(this, args)))
         };
-        let fptr = move |val: Vec<Value>| fptr(val).into();
         let mut args = F::multi_ty();
-        args.insert(0, <&mut T as FromValue>::out_ty());
-        self.ty.methods.push(Function {
-            exec: Arc::new(fptr),
-            ident: ident.into(),
-            args,
-            ret: V::in_ty(),
-        });
+        args.insert(0, ValueTy::CustomMut);
+
+        self.push(ident, args, V::in_ty(), fptr);
     }
 
     pub fn with_method_mut<C, F, V>(mut self, ident: &'static str, f: C) -> Self
     where
-        C: Fn(&mut T, F) -> V + 'static,
+        C: Fn(&mut I, F) -> V + Captures<M> + 'static,
         F: for<'a> FromMultiValue<'a>,
         V: IntoValue,
     {
@@ -158,93 +1514,770 @@ where
         self
     }
 
-    pub fn add_property<P>(&mut self, builder: PropertyBuilder<T, P>) {
-        self.ty.properties.push(builder.property);
+    pub fn set_catch_unwind(&mut self, catch_unwind: bool) {
+        self.catch_unwind = catch_unwind;
     }
 
-    pub fn with_property<P>(mut self, builder: PropertyBuilder<T, P>) -> Self {
-        self.add_property(builder);
+    pub fn with_catch_unwind(mut self, catch_unwind: bool) -> Self {
+        self.set_catch_unwind(catch_unwind);
 
         self
     }
+
+    fn push<E>(&mut self, ident: &'static str, args: Vec<ValueTy>, ret: ValueTy, exec: E)
+    where
+        E: Fn(Vec<Value>) -> Result<Value<'static>> + 'static,
+    {
+        let context = Str::from(format!("{}.{}", self.ident, ident));
+        let catch_unwind = self.catch_unwind;
+        let fptr = move |val: Vec<Value>| {
+            guard(catch_unwind, || exec(val))
+                .map_err(|e| call_error(&context, e))
+                .into()
+        };
+
+        self.methods.push(Function {
+            exec: Arc::new(fptr),
+            ident: Str::from(ident),
+            args,
+            arg_names: vec![],
+            defaults: vec![],
+            ret,
+        });
+    }
 }
 
-#[must_use]
-pub struct PropertyBuilder<T, P> {
-    property: Property,
-    marker: PhantomData<(T, P)>,
+fn with_this<T, F, R>(this: Value, f: F) -> Result<R>
+where
+    T: Any,
+    F: FnOnce(&T) -> Result<R>,
+{
+    match this {
+        Value::Shared(s) => s.with_ref(|any| {
+            <&T as FromValue>::from(Value::CustomRef(any))
+                .map_err(self_error)
+                .and_then(f)
+        }).map_err(self_error)?,
+        other => <&T as FromValue>::from(other).map_err(self_error).and_then(f),
+    }
 }
 
-impl<T, P> PropertyBuilder<T, P>
+fn with_this_mut<T, F, R>(this: Value, f: F) -> Result<R>
 where
     T: Any,
-    P: for<'a> FromValue<'a> + IntoValue,
+    F: FnOnce(&mut T) -> Result<R>,
 {
-    pub fn new(ident: &'static str) -> Self {
-        PropertyBuilder {
-            property: Property {
-                ident: ident.into(),
-                ty: P::out_ty(),
-                get: None,
-                set: None,
+    match this {
+        Value::Shared(s) => s.with_mut(|any| {
+            <&mut T as FromValue>::from(Value::CustomMut(any))
+                .map_err(self_error)
+                .and_then(f)
+        }).map_err(self_error)?,
+        other => <&mut T as FromValue>::from(other).map_err(self_error).and_then(f),
+    }
+}
+
+fn with_dyn<I, F, R>(this: Value, f: F) -> Result<R>
+where
+    I: Interface + ?Sized,
+    F: FnOnce(&I) -> Result<R>,
+{
+    match this {
+        Value::Shared(s) => s.with_ref(|any| {
+            interface::cast(any).map_err(self_error).and_then(f)
+        }).map_err(self_error)?,
+        Value::CustomRef(any) => interface::cast(any).map_err(self_error).and_then(f),
+        other => Err(self_error(Error::WrongType {
+            expected: ValueTy::CustomRef,
+            found: other.ty(),
+        })),
+    }
+}
+
+fn with_dyn_mut<I, F, R>(this: Value, f: F) -> Result<R>
+where
+    I: Interface + ?Sized,
+    F: FnOnce(&mut I) -> Result<R>,
+{
+    match this {
+        Value::Shared(s) => s.with_mut(|any| {
+            interface::cast_mut(any).map_err(self_error).and_then(f)
+        }).map_err(self_error)?,
+        Value::CustomMut(any) => interface::cast_mut(any).map_err(self_error).and_then(f),
+        other => Err(self_error(Error::WrongType {
+            expected: ValueTy::CustomMut,
+            found: other.ty(),
+        })),
+    }
+}
+
+fn with_context(context: Str, catch_unwind: bool, fptr: FunPtr) -> FunPtr {
+    Arc::new(move |val: Vec<Value>| {
+        guard(catch_unwind, || fptr(val).into_res())
+            .map_err(|e| call_error(&context, e))
+            .into()
+    })
+}
+
+fn guard<F, R>(catch_unwind: bool, f: F) -> Result<R>
+where
+    F: FnOnce() -> Result<R>,
+{
+    if !catch_unwind {
+        return f();
+    }
+
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = match payload.downcast::<String>() {
+            Ok(s) => Str::from(*s),
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(s) => Str::from(*s),
+                Err(_) => Str::from("unknown panic payload"),
             },
-            marker: PhantomData,
+        };
+
+        Err(Error::Panicked { message })
+    })
+}
+
+fn call_error(context: &Str, error: Error) -> Error {
+    Error::Call {
+        ident: context.clone(),
+        error: Box::new(error),
+    }
+}
+
+fn name_args(error: Error, names: &[Str]) -> Error {
+    match error {
+        Error::Call { ident, error } => {
+            let error = match *error {
+                Error::Argument {
+                    index,
+                    name: None,
+                    error,
+                } => Error::Argument {
+                    index,
+                    name: names.get(index).cloned(),
+                    error,
+                },
+                other => other,
+            };
+
+            Error::Call {
+                ident,
+                error: Box::new(error),
+            }
         }
+        other => other,
     }
+}
 
-    pub fn add_getter<G>(&mut self, get: G)
-    where
-        G: Fn(&T) -> P + 'static,
-    {
-        let fptr = move |val: Vec<Value>| {
-            let mut deque: MultiVal = val.into();
-            let this = <&T as FromValue>::from(deque.pop_front().ok_or(Error::MissingSelfArg)?)?;
+fn self_error(error: Error) -> Error {
+    Error::Argument {
+        index: 0,
+        name: None,
+        error: Box::new(error),
+    }
+}
+
+fn shift_args(error: Error, by: usize) -> Error {
+    match error {
+        Error::Argument { index, name, error } => Error::Argument {
+            index: index + by,
+            name,
+            error,
+        },
+        Error::WrongArgsNumber { expected, found } => Error::WrongArgsNumber {
+            expected: expected + by as u16,
+            found: found + by as u16,
+        },
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use Shared;
 
-            P::into(get(this))
+    struct Foo(i32);
+
+    fn foo_ty() -> Ty {
+        foo_ty_builder().ty
+    }
+
+    fn foo_ty_builder() -> TyBuilder<Foo> {
+        TyBuilder::<Foo>::new("Foo")
+            .with_method("get", |foo, ()| foo.0)
+            .with_method_mut("add", |foo, (x,): (i32,)| foo.0 += x)
+            .with_method("sum_up", |foo, (a, b): (i32, i32)| foo.0 + a + b)
+    }
+
+    #[test]
+    fn catch_unwind() {
+        let ty = TyBuilder::<Foo>::new("Foo")
+            .with_method_mut("explode", |_, ()| -> i32 { panic!("boom") })
+            .ty;
+        let foo = Rc::new(RefCell::new(Foo(0)));
+
+        match (ty.methods[0].exec)(vec![Value::Shared(Box::new(foo.clone()))]) {
+            Value::Error(ref e) => assert_eq!(e.to_string(), "Foo.explode: panicked: boom"),
+            other => panic!("Expected panic error, got {:?}", other),
+        }
+        assert!(foo.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    fn optional_and_defaults() {
+        let ty = TyBuilder::<Foo>::new("Foo")
+            .with_function("spawn", |(x, layer): (i32, Option<i32>)| x + layer.unwrap_or(10))
+            .with_function("scale", |(x, by, plus): (i32, i32, i32)| x * by + plus)
+            .with_defaults((2, 1))
+            .ty;
+        let call = |f: &Function, args: Vec<Value<'static>>| (f.exec)(args);
+
+        let spawn = &ty.functions[0];
+        assert_eq!(spawn.min_args(), 1);
+        assert_eq!(call(spawn, vec![Value::Int32(1)]), Value::Int32(11));
+        assert_eq!(call(spawn, vec![Value::Int32(1), Value::Int32(2)]), Value::Int32(3));
+
+        let scale = &ty.functions[1];
+        assert_eq!(scale.min_args(), 1);
+        assert_eq!(scale.defaults.len(), 2);
+        assert_eq!((scale.defaults[0])(), Value::Int32(2));
+        assert_eq!(call(scale, vec![Value::Int32(3)]), Value::Int32(7));
+        assert_eq!(call(scale, vec![Value::Int32(3), Value::Int32(3)]), Value::Int32(10));
+        match call(scale, vec![]) {
+            Value::Error(ref e) => {
+                assert_eq!(e.to_string(), "Foo.scale: expected 1 arguments, found 0")
+            }
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn named_arguments() {
+        let ty = TyBuilder::<Foo>::new("Foo")
+            .with_function_named(
+                "spawn",
+                &["x", "y", "layer"],
+                |(x, y, layer): (i32, i32, Option<i32>)| x * 100 + y * 10 + layer.unwrap_or(0),
+            )
+            .ty;
+        let spawn = &ty.functions[0];
+        let named = |pairs: Vec<(&'static str, Value<'static>)>| {
+            pairs
+                .into_iter()
+                .map(|(name, val)| (Str::from(name), val))
+                .collect::<Vec<_>>()
         };
-        let fptr = move |val: Vec<Value>| fptr(val).into();
 
-        self.property.get = Some(Arc::new(fptr));
+        assert_eq!(spawn.arg_names, vec!["x", "y", "layer"]);
+
+        let order = |positional, pairs| {
+            spawn.order_named(positional, named(pairs), || Value::Nil, Ok)
+        };
+
+        let args = order(
+            vec![Value::Int32(1)],
+            vec![("layer", Value::Int32(3)), ("y", Value::Int32(2))],
+        ).unwrap();
+        assert_eq!((spawn.exec)(args), Value::Int32(123));
+
+        let args = order(vec![], vec![("y", Value::Int32(2)), ("x", Value::Int32(1))]).unwrap();
+        assert_eq!((spawn.exec)(args), Value::Int32(120));
+
+        match order(vec![], vec![("layer", Value::Int32(3))]) {
+            Err(Error::MissingArgument { ref name }) if name == "x" => {}
+            other => panic!("Expected missing argument, got {:?}", other),
+        }
+        match order(vec![Value::Int32(1)], vec![("x", Value::Int32(1))]) {
+            Err(Error::DuplicateArgument { ref name }) if name == "x" => {}
+            other => panic!("Expected duplicate argument, got {:?}", other),
+        }
+
+        let args = vec![Value::Int32(1), Value::Bool(true)];
+        match (spawn.exec)(args) {
+            Value::Error(ref e) => assert_eq!(
+                e.to_string(),
+                "Foo.spawn: argument 1 (y): expected Int32, found Bool"
+            ),
+            other => panic!("Expected error, got {:?}", other),
+        }
     }
 
-    pub fn with_getter<G>(mut self, get: G) -> Self
-        where
-            G: Fn(&T) -> P + 'static,
-    {
-        self.add_getter(get);
+    #[test]
+    fn named_defaults() {
+        let ty = TyBuilder::<Foo>::new("Foo")
+            .with_function_named("scale", &["x", "by", "plus"], |(x, by, plus): (i32, i32, i32)| {
+                x * by + plus
+            })
+            .with_defaults((2, 1))
+            .ty;
+        let scale = &ty.functions[0];
+        let named = vec![(Str::from("plus"), Value::Int32(5))];
 
-        self
+        let args = scale.order_named(vec![Value::Int32(3)], named, || Value::Nil, Ok).unwrap();
+        assert_eq!(args[1], Value::Int32(2));
+        assert_eq!((scale.exec)(args), Value::Int32(11));
+
+        let named = vec![(Str::from("by"), Value::Int32(4))];
+        match scale.order_named(vec![], named, || Value::Nil, Ok) {
+            Err(Error::MissingArgument { ref name }) if name == "x" => {}
+            other => panic!("Expected missing argument, got {:?}", other),
+        }
     }
 
-    pub fn add_setter<S>(&mut self, set: S)
-        where
-            S: Fn(&mut T, P) -> () + 'static,
-    {
-        let fptr = move |val: Vec<Value>| {
-            let mut deque: MultiVal = val.into();
-            let this =
-                <&mut T as FromValue>::from(deque.pop_front().ok_or(Error::MissingSelfArg)?)?;
+    #[test]
+    fn error_context() {
+        let ty = foo_ty();
+        let foo = || Value::Custom(Box::new(Foo(1)));
+
+        let err = (ty.methods[2].exec)(vec![foo(), Value::Int32(2), Value::String(Str::from("3"))])
+            .into_res()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Foo.sum_up: argument 2: expected Int32, found String"
+        );
+
+        let err = (ty.methods[2].exec)(vec![foo(), Value::Int32(2)])
+            .into_res()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Foo.sum_up: expected 3 arguments, found 2");
+
+        let err = (ty.methods[0].exec)(vec![Value::Bool(true)])
+            .into_res()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Foo.get: argument 0: expected CustomRef, found Bool");
+    }
+
+    #[test]
+    fn accessors() {
+        let mut ty = TyBuilder::<Foo>::new("Foo")
+            .with_catch_unwind(false)
+            .with_function("one", |()| 1);
+        assert_eq!(ty.ty().functions[0].ident, "one");
+        assert!(!ty.catch_unwind());
+        ty.ty_mut().functions.clear();
 
-            let value = P::from(deque.pop_front().ok_or(Error::WrongArgsNumber {
-                expected: 1,
-                found: 0,
-            })?)?;
+        let mut builder = Builder::<Local>::default().with_ty(ty);
+        assert_eq!(builder.map()[&TypeId::of::<Foo>()].ident, "Foo");
+        assert!(builder.map()[&TypeId::of::<Foo>()].functions.is_empty());
+        builder.map_mut().clear();
+        assert!(builder.map().is_empty());
+    }
 
-            set(this, value);
+    #[test]
+    fn coercion_policy() {
+        let build = |coercion| {
+            let module = ModuleBuilder::new("math").with_function("half", |(x,): (i32,)| x / 2);
+            let (map, root) = Builder::<Local>::default()
+                .with_ty(TyBuilder::<Foo>::new("Foo").with_function("scale", |(f,): (f32,)| f))
+                .with_module(module)
+                .with_coercion(coercion)
+                .finish_with_modules()
+                .unwrap();
 
-            Ok(())
+            (map[&TypeId::of::<Foo>()].functions[0].clone(), root.modules[0].functions[0].clone())
         };
-        let fptr = move |val: Vec<Value>| fptr(val).map(|_| Value::Void).into();
 
-        self.property.set = Some(Arc::new(fptr));
+        let (scale, half) = build(Coercion::strict());
+        let err = (half.exec)(vec![Value::Float64(4.0)]).into_res().unwrap_err();
+        assert_eq!(err.to_string(), "math.half: argument 0: expected Int32, found Float64");
+        let err = (scale.exec)(vec![Value::Int64(2)]).into_res().unwrap_err();
+        assert_eq!(err.to_string(), "Foo.scale: argument 0: expected Float32, found Int64");
+        assert_eq!((half.exec)(vec![Value::Int8(4)]), Value::Int32(2));
+
+        let (scale, half) = build(Coercion::lenient());
+        assert_eq!((half.exec)(vec![Value::Float64(5.5)]), Value::Int32(2));
+        assert_eq!((scale.exec)(vec![Value::Int64(16_777_217)]), Value::Float32(16_777_216.0));
+
+        let (_, half) = build(Coercion::default());
+        let err = (half.exec)(vec![Value::Float64(5.5)]).into_res().unwrap_err();
+        assert!(err.to_string().starts_with("math.half: argument 0:"));
     }
 
-    pub fn with_setter<S>(mut self, set: S) -> Self
-    where
-        S: Fn(&mut T, P) -> () + 'static,
-    {
-        self.add_setter(set);
+    #[test]
+    fn nested_coercion() {
+        use std::collections::HashMap;
+        use {Callback, Rest};
 
-        self
+        let build = |coercion| {
+            let ty = TyBuilder::<Foo>::new("Foo")
+                .with_function("sum", |(v,): (Vec<i32>,)| v.iter().sum::<i32>())
+                .with_function("max", |(a, rest): (i32, Rest<i32>)| {
+                    rest.iter().fold(a, |m, &x| m.max(x))
+                })
+                .with_function("get", |(m,): (HashMap<String, i32>,)| m["a"])
+                .with_function("apply", |(cb,): (Callback<(i32,), i32>,)| cb.call((2,)));
+            let map = Builder::<Local>::default()
+                .with_ty(ty)
+                .with_coercion(coercion)
+                .finish()
+                .unwrap();
+
+            map[&TypeId::of::<Foo>()].functions.clone()
+        };
+        let floats = || Value::Array(vec![Value::Float64(1.0), Value::Float64(2.5)]);
+        let entry = || Value::Map(vec![(Value::String(Str::from("a")), Value::Float64(7.9))]);
+        let half = || Value::Function(Function::new("half", |(x,): (i32,)| x as f64 / 2.0));
+
+        let funcs = build(Coercion::lenient());
+        assert_eq!((funcs[0].exec)(vec![floats()]), Value::Int32(3));
+        let args = vec![Value::Int32(1), Value::Float64(2.0), Value::Float64(3.7)];
+        assert_eq!((funcs[1].exec)(args), Value::Int32(3));
+        assert_eq!((funcs[2].exec)(vec![entry()]), Value::Int32(7));
+        assert_eq!((funcs[3].exec)(vec![half()]), Value::Int32(1));
+
+        let funcs = build(Coercion::strict());
+        let err = (funcs[0].exec)(vec![floats()]).into_res().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Foo.sum: argument 0: element 0: expected Int32, found Float64"
+        );
+        let args = vec![Value::Int32(1), Value::Float64(2.0)];
+        assert!((funcs[1].exec)(args).into_res().is_err());
+        assert!((funcs[2].exec)(vec![entry()]).into_res().is_err());
+    }
+
+    #[test]
+    fn return_closure() {
+        let ty = TyBuilder::<Foo>::new("Foo")
+            .with_function("greater_than", |(min,): (i32,)| {
+                Box::new(move |(x,): (i32,)| x > min) as Box<Fn((i32,)) -> bool>
+            })
+            .ty;
+
+        let f = &ty.functions[0];
+        assert_eq!(
+            f.ret,
+            ValueTy::Function(vec![ValueTy::Int32], Box::new(ValueTy::Bool))
+        );
+
+        match (f.exec)(vec![Value::Int32(3)]) {
+            Value::Function(filter) => {
+                assert_eq!(filter.args, vec![ValueTy::Int32]);
+                assert_eq!(filter.ret, ValueTy::Bool);
+                assert_eq!((filter.exec)(vec![Value::Int32(4)]), Value::Bool(true));
+                assert_eq!((filter.exec)(vec![Value::Int32(2)]), Value::Bool(false));
+            }
+            other => panic!("Expected function, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shared_methods() {
+        let ty = foo_ty();
+        let foo = Rc::new(RefCell::new(Foo(3)));
+        let shared = || Value::Shared(Box::new(foo.clone()) as Box<Shared>);
+
+        (ty.methods[1].exec)(vec![shared(), Value::Int32(4)]).into_res().unwrap();
+        match (ty.methods[0].exec)(vec![shared()]) {
+            Value::Int32(7) => {}
+            other => panic!("Expected 7, got {:?}", other),
+        }
+
+        let _guard = foo.borrow_mut();
+        match (ty.methods[0].exec)(vec![shared()]) {
+            Value::Error(Error::Call { ref error, .. }) => match **error {
+                Error::Argument {
+                    index: 0,
+                    ref error,
+                    ..
+                } => match **error {
+                    Error::AlreadyBorrowed { .. } => {}
+                    ref other => panic!("Expected borrow error, got {:?}", other),
+                },
+                ref other => panic!("Expected self argument error, got {:?}", other),
+            },
+            other => panic!("Expected borrow error, got {:?}", other),
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum KeyCode {
+        Escape = 27,
+        Enter,
+    }
+
+    enum Shape {
+        Circle { r: f32 },
+        Square(f32),
+    }
+
+    fn shared<T: Any>(value: T) -> Value<'static> {
+        Value::Shared(Box::new(Rc::new(RefCell::new(value))))
+    }
+
+    fn find<'a>(funcs: &'a [Function], ident: &str) -> &'a Function {
+        funcs.iter().find(|f| f.ident == ident).unwrap()
+    }
+
+    #[test]
+    fn c_like_enum() {
+        let ty = EnumBuilder::<KeyCode>::new("KeyCode")
+            .with_unit("Escape", KeyCode::Escape)
+            .with_discriminant(27)
+            .with_unit("Enter", KeyCode::Enter)
+            .build()
+            .ty;
+        let key = |value: Value<'static>| match value {
+            Value::Custom(b) => *b.downcast::<KeyCode>().ok().unwrap(),
+            other => panic!("Expected KeyCode, got {:?}", other),
+        };
+
+        assert_eq!(ty.variants[1].ident, "Enter");
+        assert_eq!(ty.variants[1].discriminant, Some(28));
+        let enter = || match ty.variants[1].kind {
+            VariantKind::Unit(ref make) => make(),
+            _ => panic!("Expected unit variant"),
+        };
+        assert_eq!(key(enter()), KeyCode::Enter);
+
+        let variant = find(&ty.methods, "variant");
+        assert_eq!((variant.exec)(vec![shared(KeyCode::Enter)]), Value::String(Str::from("Enter")));
+        let to_int = find(&ty.methods, "to_int");
+        assert_eq!((to_int.exec)(vec![shared(KeyCode::Enter)]), Value::Int64(28));
+
+        let from_int = find(&ty.functions, "from_int");
+        assert_eq!(key((from_int.exec)(vec![Value::Int64(27)])), KeyCode::Escape);
+        assert_eq!((from_int.exec)(vec![Value::Int64(3)]), Value::Nil);
+        let from_name = find(&ty.functions, "from_name");
+        let name = Value::String(Str::from("Escape"));
+        assert_eq!(key((from_name.exec)(vec![name])), KeyCode::Escape);
+    }
+
+    #[test]
+    fn data_enum() {
+        let shape = TyBuilder::<Shape>::new("Shape").with_method("area", |shape, ()| match *shape {
+            Shape::Circle { r } => 3.0 * r * r,
+            Shape::Square(side) => side * side,
+        });
+        let ty = EnumBuilder::from_ty(shape)
+            .with_variant("Circle", |(r,): (f32,)| Shape::Circle { r })
+            .with_variant("Square", |(side,): (f32,)| Shape::Square(side))
+            .with_variant_of(|shape| match *shape {
+                Shape::Circle { .. } => "Circle",
+                Shape::Square(_) => "Square",
+            })
+            .build()
+            .ty;
+
+        assert!(ty.variants.iter().all(|v| v.discriminant.is_none()));
+        assert!(ty.functions.iter().all(|f| f.ident != "from_int"));
+        let circle = match ty.variants[0].kind {
+            VariantKind::Constructor(ref f) => (f.exec)(vec![Value::Float32(2.0)]),
+            _ => panic!("Expected constructor"),
+        };
+        let circle = match circle {
+            Value::Custom(b) => *b.downcast::<Shape>().ok().unwrap(),
+            other => panic!("Expected Shape, got {:?}", other),
+        };
+        let area = find(&ty.methods, "area");
+        assert_eq!((area.exec)(vec![shared(circle)]), Value::Float32(12.0));
+        let variant = find(&ty.methods, "variant");
+        let square = shared(Shape::Square(1.0));
+        assert_eq!((variant.exec)(vec![square]), Value::String(Str::from("Square")));
+    }
+
+    struct Wrapper(Foo);
+
+    impl ::std::ops::Deref for Wrapper {
+        type Target = Foo;
+
+        fn deref(&self) -> &Foo {
+            &self.0
+        }
+    }
+
+    impl DerefMut for Wrapper {
+        fn deref_mut(&mut self) -> &mut Foo {
+            &mut self.0
+        }
+    }
+
+    #[test]
+    fn parent_delegation() {
+        let mut foo = TyBuilder::<Foo>::new("Foo")
+            .with_method("get", |foo, ()| foo.0)
+            .with_method_mut("add", |foo, (x,): (i32,)| foo.0 += x)
+            .with_property(PropertyBuilder::new("value").with_getter(|foo: &Foo| foo.0));
+        foo.ty.operators.push((
+            Operator::Sub,
+            Function {
+                exec: Arc::new(|val: Vec<Value>| {
+                    let get = |v: Value| <&Foo as FromValue>::from(v).map(|foo| foo.0);
+                    let mut val = val.into_iter();
+                    let lhs = get(val.next().unwrap());
+                    let rhs = get(val.next().unwrap());
+
+                    lhs.and_then(|a| rhs.map(|b| Value::Int32(a - b))).into()
+                }),
+                ident: Str::from("__sub"),
+                args: vec![ValueTy::CustomRef, ValueTy::CustomRef],
+                arg_names: vec![],
+                defaults: vec![],
+                ret: ValueTy::Int32,
+            },
+        ));
+        let wrapper = TyBuilder::<Wrapper>::new("Wrapper")
+            .with_parent()
+            .with_method("get", |w, ()| w.0 .0 * 100);
+        let map = Builder::default().with_ty(foo).with_ty(wrapper).finish().unwrap();
+        let ty = &map[&TypeId::of::<Wrapper>()];
+
+        let parent = ty.parent.as_ref().unwrap();
+        assert_eq!(parent.ident, "Foo");
+        assert_eq!(parent.inherited, vec!["add", "value"]);
+
+        let wrapped = Rc::new(RefCell::new(Wrapper(Foo(1))));
+        let shared = || Value::Shared(Box::new(wrapped.clone()));
+        let add = find(&ty.methods, "add");
+        (add.exec)(vec![shared(), Value::Int32(2)]).into_res().unwrap();
+        assert_eq!(wrapped.borrow().0 .0, 3);
+        assert_eq!((find(&ty.methods, "get").exec)(vec![shared()]), Value::Int32(300));
+        let get = ty.properties[0].get.as_ref().unwrap();
+        assert_eq!(get(vec![shared()]), Value::Int32(3));
+        let (a, b) = (Wrapper(Foo(5)), Wrapper(Foo(2)));
+        let sub = &ty.operators[0].1;
+        assert_eq!((sub.exec)(vec![Value::CustomRef(&a), Value::CustomRef(&b)]), Value::Int32(3));
+
+        let _guard = wrapped.borrow_mut();
+        match (add.exec)(vec![shared(), Value::Int32(2)]) {
+            Value::Error(ref e) => assert!(e.to_string().starts_with("Wrapper.add: ")),
+            other => panic!("Expected borrow error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_parent() {
+        let wrapper = TyBuilder::<Wrapper>::new("Wrapper").with_parent();
+        match Builder::default().with_ty(wrapper).finish() {
+            Err(e) => assert_eq!(e.to_string(), "the parent of Wrapper has not been registered"),
+            Ok(_) => panic!("Expected missing parent"),
+        }
+    }
+
+    #[test]
+    fn operators() {
+        let foo = TyBuilder::<Foo>::new("Foo")
+            .with_operator(Operator::Add, |foo, (x,): (i32,)| foo.0 + x)
+            .with_operator(Operator::Neg, |foo, ()| -foo.0)
+            .with_operator_mut(Operator::NewIndex, |foo, (_, x): (String, i32)| foo.0 = x)
+            .with_operator(Operator::ToString, |foo, ()| format!("Foo({})", foo.0));
+        let map = Builder::default().with_ty(foo).finish().unwrap();
+        let ty = &map[&TypeId::of::<Foo>()];
+        let op = |op: Operator| &ty.operators.iter().find(|&&(o, _)| o == op).unwrap().1;
+
+        let foo = Rc::new(RefCell::new(Foo(2)));
+        let shared = || Value::Shared(Box::new(foo.clone()));
+        assert_eq!(op(Operator::Neg).ident, "neg");
+        assert_eq!((op(Operator::Add).exec)(vec![shared(), Value::Int32(3)]), Value::Int32(5));
+        let key = Value::String(Str::from("x"));
+        (op(Operator::NewIndex).exec)(vec![shared(), key, Value::Int32(7)])
+            .into_res()
+            .unwrap();
+        assert_eq!(
+            (op(Operator::ToString).exec)(vec![shared()]),
+            Value::String(Str::from("Foo(7)"))
+        );
+
+        let ambiguous = TyBuilder::<Foo>::new("Foo")
+            .with_operator(Operator::Add, |foo, (x,): (i32,)| foo.0 + x)
+            .with_operator(Operator::Add, |foo, (x,): (i64,)| foo.0 as i64 + x);
+        assert!(Builder::default().with_ty(ambiguous).finish().is_err());
+    }
+
+    #[derive(Clone)]
+    struct Color(u8, u8, u8);
+
+    #[test]
+    fn constants() {
+        let ty = TyBuilder::<Color>::new("Color")
+            .with_constant("MAX", 255u8)
+            .with_constant("RED", Box::new(Color(255, 0, 0)))
+            .ty;
+
+        assert_eq!(ty.constants[0].ident, "MAX");
+        assert_eq!(ty.constants[0].ty, ValueTy::Uint8);
+        assert_eq!((ty.constants[0].value)(), Value::Uint8(255));
+
+        assert_eq!(ty.constants[1].ty, ValueTy::Custom);
+        let rgb = |value: Value<'static>| match value {
+            Value::Custom(b) => {
+                let color = b.downcast::<Color>().ok().unwrap();
+                (color.0, color.1, color.2)
+            }
+            other => panic!("Expected Color, got {:?}", other),
+        };
+        assert_eq!(rgb((ty.constants[1].value)()), (255, 0, 0));
+        assert_eq!(rgb((ty.constants[1].value)()), (255, 0, 0));
+    }
+
+    #[test]
+    fn modules() {
+        let audio = ModuleBuilder::new("audio")
+            .with_function("play", |(volume,): (i32,)| volume * 2)
+            .with_ty(TyBuilder::<Color>::new("Sound").with_function("new", |(_,): (i32,)| ()));
+        let engine = ModuleBuilder::new("engine")
+            .with_constant("VERSION", 3)
+            .with_function("log", |(_,): (String,)| ())
+            .with_module(audio);
+        let (map, root) = Builder::default()
+            .with_ty(foo_ty_builder())
+            .with_module(engine)
+            .finish_with_modules()
+            .unwrap();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(root.types, vec![TypeId::of::<Foo>()]);
+        let engine = &root.modules[0];
+        assert_eq!(engine.ident, "engine");
+        assert_eq!((engine.constants[0].value)(), Value::Int32(3));
+        let audio = &engine.modules[0];
+        assert_eq!(audio.types, vec![TypeId::of::<Color>()]);
+        assert_eq!((audio.functions[0].exec)(vec![Value::Int32(4)]), Value::Int32(8));
+        match (audio.functions[0].exec)(vec![]) {
+            Value::Error(ref e) => assert!(e.to_string().starts_with("engine.audio.play: ")),
+            other => panic!("Expected error, got {:?}", other),
+        }
+        match (engine.functions[0].exec)(vec![]) {
+            Value::Error(ref e) => assert!(e.to_string().starts_with("engine.log: ")),
+            other => panic!("Expected error, got {:?}", other),
+        }
+        let sound = &map[&TypeId::of::<Color>()];
+        match (sound.functions[0].exec)(vec![Value::Bool(true)]) {
+            Value::Error(ref e) => assert!(e.to_string().starts_with("engine.audio.Sound.new: ")),
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn module_paths() {
+        let color = || TyBuilder::<Color>::new("Color").with_function("new", |(_,): (i32,)| ());
+        let audio = ModuleBuilder::new("audio")
+            .with_function("play", |(volume,): (i32,)| volume * 2)
+            .with_ty(color());
+        let (map, root) = Builder::default()
+            .with_module(ModuleBuilder::new("engine").with_module(audio))
+            .with_module(ModuleBuilder::new("ui").with_ty(color()))
+            .with_coercion(Coercion::strict())
+            .finish_with_modules()
+            .unwrap();
+
+        let play = &root.modules[0].modules[0].functions[0];
+        let err = (play.exec)(vec![Value::Float64(4.0)]).into_res().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "engine.audio.play: argument 0: expected Int32, found Float64"
+        );
+
+        let new = &map[&TypeId::of::<Color>()].functions[0];
+        let err = (new.exec)(vec![Value::Bool(true)]).into_res().unwrap_err();
+        assert!(err.to_string().starts_with("engine.audio.Color.new: "), "{}", err);
     }
 }
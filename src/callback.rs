@@ -0,0 +1,104 @@
+use std::marker::PhantomData;
+
+use conv::{FromValue, IntoMultiValue};
+use {Error, Function, Result, Value, ValueTy};
+
+pub struct Callback<A, R> {
+    func: Function,
+    marker: PhantomData<fn(A) -> R>,
+}
+
+impl<A, R> Callback<A, R>
+where
+    A: IntoMultiValue,
+    R: FromValue<'static>,
+{
+    pub fn call(&self, args: A) -> Result<R> {
+        let args = args.into_multi()?;
+
+        R::from((self.func.exec)(args))
+    }
+
+    pub fn function(&self) -> &Function {
+        &self.func
+    }
+}
+
+impl<A, R> Clone for Callback<A, R> {
+    fn clone(&self) -> Self {
+        Callback {
+            func: self.func.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, A, R> FromValue<'a> for Callback<A, R>
+where
+    A: IntoMultiValue,
+    R: FromValue<'static>,
+{
+    fn out_ty() -> ValueTy {
+        ValueTy::Function(A::multi_in_ty(), Box::new(R::out_ty()))
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match v.into_res()? {
+            Value::Function(func) => Ok(Callback {
+                func,
+                marker: PhantomData,
+            }),
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn is_even() -> Value<'static> {
+        Value::Function(Function {
+            exec: Arc::new(|args: Vec<Value>| match args[0] {
+                Value::Int32(i) => Value::Bool(i % 2 == 0),
+                ref other => Value::Error(Error::WrongType {
+                    expected: ValueTy::Int32,
+                    found: other.ty(),
+                }),
+            }),
+            ident: "is_even".into(),
+            args: vec![ValueTy::Int32],
+            arg_names: vec![],
+            defaults: vec![],
+            ret: ValueTy::Bool,
+        })
+    }
+
+    #[test]
+    fn call() {
+        let cb = <Callback<(i32,), bool> as FromValue>::from(is_even()).unwrap();
+
+        assert_eq!(cb.call((4,)).unwrap(), true);
+        assert_eq!(cb.clone().call((5,)).unwrap(), false);
+        assert_eq!(
+            <Callback<(i32,), bool> as FromValue>::out_ty(),
+            ValueTy::Function(vec![ValueTy::Int32], Box::new(ValueTy::Bool))
+        );
+    }
+
+    #[test]
+    fn wrong_return() {
+        let cb = <Callback<(i32,), String> as FromValue>::from(is_even()).unwrap();
+
+        match cb.call((4,)) {
+            Err(Error::WrongType { .. }) => {}
+            Err(e) => panic!("Expected wrong type, got {:?}", e),
+            Ok(s) => panic!("Expected wrong type, got {:?}", s),
+        }
+    }
+}
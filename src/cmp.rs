@@ -0,0 +1,224 @@
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use fnv::FnvHasher;
+
+use {Error, Result, Value};
+
+impl<'a, 'b> PartialEq<Value<'b>> for Value<'a> {
+    fn eq(&self, other: &Value<'b>) -> bool {
+        plain_eq(self, other, false)
+    }
+}
+
+#[derive(Debug)]
+pub struct HashKey(Value<'static>);
+
+impl HashKey {
+    pub fn new(val: &Value) -> Result<Self> {
+        to_plain(val).map(HashKey)
+    }
+
+    pub fn value(&self) -> &Value<'static> {
+        &self.0
+    }
+
+    pub fn into_value(self) -> Value<'static> {
+        self.0
+    }
+}
+
+impl PartialEq for HashKey {
+    fn eq(&self, other: &HashKey) -> bool {
+        plain_eq(&self.0, &other.0, true)
+    }
+}
+
+impl Eq for HashKey {}
+
+impl Hash for HashKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_plain(&self.0, state);
+    }
+}
+
+fn plain_eq(a: &Value, b: &Value, bitwise: bool) -> bool {
+    match (a, b) {
+        (&Value::Nil, &Value::Nil) => true,
+        (&Value::Void, &Value::Void) => true,
+        (&Value::Bool(a), &Value::Bool(b)) => a == b,
+        (&Value::Int8(a), &Value::Int8(b)) => a == b,
+        (&Value::Int16(a), &Value::Int16(b)) => a == b,
+        (&Value::Int32(a), &Value::Int32(b)) => a == b,
+        (&Value::Int64(a), &Value::Int64(b)) => a == b,
+        (&Value::Uint8(a), &Value::Uint8(b)) => a == b,
+        (&Value::Uint16(a), &Value::Uint16(b)) => a == b,
+        (&Value::Uint32(a), &Value::Uint32(b)) => a == b,
+        (&Value::Uint64(a), &Value::Uint64(b)) => a == b,
+        (&Value::Float32(a), &Value::Float32(b)) if bitwise => a.to_bits() == b.to_bits(),
+        (&Value::Float32(a), &Value::Float32(b)) => a == b,
+        (&Value::Float64(a), &Value::Float64(b)) if bitwise => a.to_bits() == b.to_bits(),
+        (&Value::Float64(a), &Value::Float64(b)) => a == b,
+        (&Value::String(ref a), &Value::String(ref b)) => a == b,
+        (&Value::Tuple(ref a), &Value::Tuple(ref b))
+        | (&Value::Array(ref a), &Value::Array(ref b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| plain_eq(a, b, bitwise))
+        }
+        (&Value::Map(ref a), &Value::Map(ref b)) => {
+            a.len() == b.len() && a.iter().all(|&(ref ka, ref va)| {
+                b.iter()
+                    .any(|&(ref kb, ref vb)| plain_eq(ka, kb, bitwise) && plain_eq(va, vb, bitwise))
+            })
+        }
+        _ => false,
+    }
+}
+
+fn hash_plain<H: Hasher>(val: &Value, state: &mut H) {
+    mem::discriminant(val).hash(state);
+
+    match *val {
+        Value::Bool(b) => b.hash(state),
+        Value::Int8(i) => i.hash(state),
+        Value::Int16(i) => i.hash(state),
+        Value::Int32(i) => i.hash(state),
+        Value::Int64(i) => i.hash(state),
+        Value::Uint8(i) => i.hash(state),
+        Value::Uint16(i) => i.hash(state),
+        Value::Uint32(i) => i.hash(state),
+        Value::Uint64(i) => i.hash(state),
+        Value::Float32(f) => f.to_bits().hash(state),
+        Value::Float64(f) => f.to_bits().hash(state),
+        Value::String(ref s) => s.hash(state),
+        Value::Tuple(ref v) | Value::Array(ref v) => {
+            v.len().hash(state);
+            for elem in v {
+                hash_plain(elem, state);
+            }
+        }
+        Value::Map(ref m) => {
+            let entries = m.iter().fold(0u64, |acc, &(ref k, ref v)| {
+                let mut hasher = FnvHasher::default();
+                hash_plain(k, &mut hasher);
+                hash_plain(v, &mut hasher);
+
+                acc.wrapping_add(hasher.finish())
+            });
+
+            m.len().hash(state);
+            entries.hash(state);
+        }
+        _ => {}
+    }
+}
+
+fn to_plain(val: &Value) -> Result<Value<'static>> {
+    let plain = match *val {
+        Value::Nil => Value::Nil,
+        Value::Void => Value::Void,
+        Value::Bool(b) => Value::Bool(b),
+        Value::Int8(i) => Value::Int8(i),
+        Value::Int16(i) => Value::Int16(i),
+        Value::Int32(i) => Value::Int32(i),
+        Value::Int64(i) => Value::Int64(i),
+        Value::Uint8(i) => Value::Uint8(i),
+        Value::Uint16(i) => Value::Uint16(i),
+        Value::Uint32(i) => Value::Uint32(i),
+        Value::Uint64(i) => Value::Uint64(i),
+        Value::Float32(f) => Value::Float32(f),
+        Value::Float64(f) => Value::Float64(f),
+        Value::String(ref s) => Value::String(s.clone()),
+        Value::Tuple(ref v) => Value::Tuple(v.iter().map(to_plain).collect::<Result<_>>()?),
+        Value::Array(ref v) => Value::Array(v.iter().map(to_plain).collect::<Result<_>>()?),
+        Value::Map(ref m) => Value::Map(m.iter()
+            .map(|&(ref k, ref v)| Ok((to_plain(k)?, to_plain(v)?)))
+            .collect::<Result<_>>()?),
+        ref other => return Err(Error::NotHashable { found: other.ty() }),
+    };
+
+    Ok(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use {Builder, TyBuilder, TyMapMut};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Foo(i32);
+    struct Bar;
+
+    #[test]
+    fn eq() {
+        assert_eq!(Value::Int32(1), Value::Int32(1));
+        assert_ne!(Value::Int32(1), Value::Int64(1));
+        assert_ne!(Value::Float64(::std::f64::NAN), Value::Float64(::std::f64::NAN));
+        assert_eq!(
+            Value::Array(vec![Value::String("a".into()), Value::Nil]),
+            Value::Array(vec![Value::String("a".into()), Value::Nil])
+        );
+        assert_ne!(
+            Value::Tuple(vec![Value::Bool(true)]),
+            Value::Array(vec![Value::Bool(true)])
+        );
+        assert_ne!(Value::CustomRef(&Bar), Value::CustomRef(&Bar));
+    }
+
+    #[test]
+    fn try_clone() {
+        let map = Builder::default()
+            .with_ty(TyBuilder::<Foo>::new("Foo").with_clone())
+            .with_ty(TyBuilder::<Bar>::new("Bar"))
+            .finish()
+            .unwrap();
+
+        let val = Value::Tuple(vec![Value::Int8(1), Value::String("b".into())]);
+        assert_eq!(val.try_clone(&map).unwrap(), val);
+
+        match Value::Custom(Box::new(Foo(4))).try_clone(&map).unwrap() {
+            Value::Custom(c) => assert_eq!(c.downcast_ref::<Foo>(), Some(&Foo(4))),
+            other => panic!("Expected custom, got {:?}", other),
+        }
+
+        match Value::Custom(Box::new(Bar)).try_clone(&map) {
+            Err(Error::NotCloneable { .. }) => {}
+            other => panic!("Expected not cloneable, got {:?}", other),
+        }
+
+        match Value::Custom(Box::new(Foo(4))).try_clone(&TyMapMut::default()) {
+            Err(Error::NotCloneable { .. }) => {}
+            other => panic!("Expected not cloneable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hash_key() {
+        let mut memo = HashMap::new();
+        let key = |v: Value| v.to_key().unwrap();
+
+        memo.insert(key(Value::Float64(::std::f64::NAN)), 1);
+        memo.insert(key(Value::Array(vec![Value::Int32(2)])), 2);
+
+        assert_eq!(memo.get(&key(Value::Float64(::std::f64::NAN))), Some(&1));
+        assert_eq!(memo.get(&key(Value::Array(vec![Value::Int32(2)]))), Some(&2));
+        assert_eq!(memo.get(&key(Value::Array(vec![Value::Int64(2)]))), None);
+
+        let map = |entries: &[(i32, bool)]| {
+            Value::Map(entries
+                .iter()
+                .map(|&(k, v)| (Value::Int32(k), Value::Bool(v)))
+                .collect())
+        };
+        assert_eq!(map(&[(1, true), (2, false)]), map(&[(2, false), (1, true)]));
+        assert_ne!(map(&[(1, true), (2, false)]), map(&[(2, true), (1, true)]));
+        memo.insert(key(map(&[(1, true), (2, false)])), 3);
+        assert_eq!(memo.get(&key(map(&[(2, false), (1, true)]))), Some(&3));
+
+        match Value::CustomRef(&Bar).to_key() {
+            Err(Error::NotHashable { .. }) => {}
+            other => panic!("Expected not hashable, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use conv::{entry_key, wrong_element};
+use {Error, Function, Result, Value, ValueTy};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntToFloat {
+    Never,
+    Exact,
+    Rounded,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatToInt {
+    Never,
+    Exact,
+    Truncated,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Coercion {
+    pub int_to_float: IntToFloat,
+    pub float_to_int: FloatToInt,
+}
+
+impl Coercion {
+    pub fn strict() -> Self {
+        Coercion {
+            int_to_float: IntToFloat::Never,
+            float_to_int: FloatToInt::Never,
+        }
+    }
+
+    pub fn lenient() -> Self {
+        Coercion {
+            int_to_float: IntToFloat::Rounded,
+            float_to_int: FloatToInt::Truncated,
+        }
+    }
+
+    pub fn coerce<'a>(&self, val: Value<'a>, ty: &ValueTy) -> Result<Value<'a>> {
+        let num = match Number::of(&val) {
+            Some(num) => num,
+            None => return self.coerce_nested(val, ty),
+        };
+        let wrong_type = || Error::WrongType {
+            expected: ty.clone(),
+            found: val.ty(),
+        };
+        let int = || self.to_int(num, ty, wrong_type);
+
+        match *ty {
+            ValueTy::Option(ref inner) | ValueTy::Rest(ref inner) => self.coerce(val, inner),
+            ValueTy::Int8 => int().and_then(|i| narrow(i, ty)).map(Value::Int8),
+            ValueTy::Int16 => int().and_then(|i| narrow(i, ty)).map(Value::Int16),
+            ValueTy::Int32 => int().and_then(|i| narrow(i, ty)).map(Value::Int32),
+            ValueTy::Int64 => int().and_then(|i| narrow(i, ty)).map(Value::Int64),
+            ValueTy::Uint8 => int().and_then(|i| narrow(i, ty)).map(Value::Uint8),
+            ValueTy::Uint16 => int().and_then(|i| narrow(i, ty)).map(Value::Uint16),
+            ValueTy::Uint32 => int().and_then(|i| narrow(i, ty)).map(Value::Uint32),
+            ValueTy::Uint64 => int().and_then(|i| narrow(i, ty)).map(Value::Uint64),
+            ValueTy::Float32 => self.to_float(num, ty, wrong_type).and_then(|f| {
+                if f.is_finite() && f.abs() > ::std::f32::MAX as f64 {
+                    Err(lossy(f, ty))
+                } else {
+                    Ok(Value::Float32(f as f32))
+                }
+            }),
+            ValueTy::Float64 => self.to_float(num, ty, wrong_type).map(Value::Float64),
+            _ => Ok(val),
+        }
+    }
+
+    fn coerce_nested<'a>(&self, val: Value<'a>, ty: &ValueTy) -> Result<Value<'a>> {
+        match (val, ty) {
+            (val, &ValueTy::Option(ref inner)) | (val, &ValueTy::Rest(ref inner)) => {
+                self.coerce(val, inner)
+            }
+            (Value::Array(elems), &ValueTy::Array(ref elem)) => {
+                self.coerce_elements(elems, |_| Some(elem)).map(Value::Array)
+            }
+            (Value::Array(elems), &ValueTy::Tuple(ref tys)) => {
+                self.coerce_elements(elems, |i| tys.get(i)).map(Value::Array)
+            }
+            (Value::Tuple(elems), &ValueTy::Tuple(ref tys)) => {
+                self.coerce_elements(elems, |i| tys.get(i)).map(Value::Tuple)
+            }
+            (Value::Map(entries), &ValueTy::Map(ref key_ty, ref value_ty)) => entries
+                .into_iter()
+                .map(|(k, v)| {
+                    let key = entry_key(&k);
+                    let wrong_entry = |error| Error::WrongEntry {
+                        key: key.clone(),
+                        error: Box::new(error),
+                    };
+
+                    Ok((
+                        self.coerce(k, key_ty).map_err(&wrong_entry)?,
+                        self.coerce(v, value_ty).map_err(&wrong_entry)?,
+                    ))
+                })
+                .collect::<Result<_>>()
+                .map(Value::Map),
+            (Value::Function(func), &ValueTy::Function(_, ref ret)) => {
+                Ok(Value::Function(self.coerce_return(func, (**ret).clone())))
+            }
+            (val, _) => Ok(val),
+        }
+    }
+
+    fn coerce_elements<'a, 't, F>(&self, elems: Vec<Value<'a>>, ty: F) -> Result<Vec<Value<'a>>>
+    where
+        F: Fn(usize) -> Option<&'t ValueTy>,
+    {
+        elems
+            .into_iter()
+            .enumerate()
+            .map(|(index, elem)| match ty(index) {
+                Some(ty) => self.coerce(elem, ty).map_err(|e| wrong_element(index, e)),
+                None => Ok(elem),
+            })
+            .collect()
+    }
+
+    fn coerce_return(&self, func: Function, ret: ValueTy) -> Function {
+        let (coercion, exec) = (*self, func.exec.clone());
+
+        Function {
+            exec: Arc::new(move |args: Vec<Value>| coercion.coerce(exec(args), &ret).into()),
+            ..func
+        }
+    }
+
+    fn to_int<W>(&self, num: Number, ty: &ValueTy, wrong_type: W) -> Result<i128>
+    where
+        W: FnOnce() -> Error,
+    {
+        match num {
+            Number::Int(i) => Ok(i),
+            Number::Float(f) => {
+                let ok = match self.float_to_int {
+                    FloatToInt::Never => return Err(wrong_type()),
+                    FloatToInt::Exact => f.is_finite() && f.fract() == 0.0,
+                    FloatToInt::Truncated => f.is_finite(),
+                };
+
+                if ok {
+                    Ok(f.trunc() as i128)
+                } else {
+                    Err(lossy(f, ty))
+                }
+            }
+        }
+    }
+
+    fn to_float<W>(&self, num: Number, ty: &ValueTy, wrong_type: W) -> Result<f64>
+    where
+        W: FnOnce() -> Error,
+    {
+        match num {
+            Number::Float(f) => Ok(f),
+            Number::Int(i) => {
+                let f = match *ty {
+                    ValueTy::Float32 => i as f32 as f64,
+                    _ => i as f64,
+                };
+
+                match self.int_to_float {
+                    IntToFloat::Never => Err(wrong_type()),
+                    IntToFloat::Exact if f as i128 != i => Err(lossy(i, ty)),
+                    _ => Ok(f),
+                }
+            }
+        }
+    }
+}
+
+impl Default for Coercion {
+    fn default() -> Self {
+        Coercion {
+            int_to_float: IntToFloat::Exact,
+            float_to_int: FloatToInt::Exact,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i128),
+    Float(f64),
+}
+
+impl Number {
+    fn of(val: &Value) -> Option<Self> {
+        let num = match *val {
+            Value::Int8(i) => Number::Int(i as i128),
+            Value::Int16(i) => Number::Int(i as i128),
+            Value::Int32(i) => Number::Int(i as i128),
+            Value::Int64(i) => Number::Int(i as i128),
+            Value::Uint8(i) => Number::Int(i as i128),
+            Value::Uint16(i) => Number::Int(i as i128),
+            Value::Uint32(i) => Number::Int(i as i128),
+            Value::Uint64(i) => Number::Int(i as i128),
+            Value::Float32(f) => Number::Float(f as f64),
+            Value::Float64(f) => Number::Float(f),
+            _ => return None,
+        };
+
+        Some(num)
+    }
+}
+
+fn narrow<T>(i: i128, ty: &ValueTy) -> Result<T>
+where
+    T: TryFrom<i128>,
+{
+    T::try_from(i).map_err(|_| lossy(i, ty))
+}
+
+fn lossy<N: ToString>(value: N, ty: &ValueTy) -> Error {
+    Error::LossyConversion {
+        value: value.to_string().into(),
+        expected: ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coerce(c: Coercion, val: Value<'static>, ty: ValueTy) -> Result<Value<'static>> {
+        c.coerce(val, &ty)
+    }
+
+    #[test]
+    fn widening() {
+        let c = Coercion::strict();
+
+        match coerce(c, Value::Int8(-3), ValueTy::Int64) {
+            Ok(Value::Int64(-3)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(c, Value::Uint32(7), ValueTy::Int64) {
+            Ok(Value::Int64(7)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(c, Value::Float32(0.5), ValueTy::Float64) {
+            Ok(Value::Float64(f)) => assert_eq!(f, 0.5),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn narrowing() {
+        let c = Coercion::strict();
+
+        match coerce(c, Value::Int64(100), ValueTy::Int8) {
+            Ok(Value::Int8(100)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(c, Value::Int64(300), ValueTy::Uint8) {
+            Err(Error::LossyConversion { ref value, .. }) if value == "300" => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(c, Value::Int64(-1), ValueTy::Uint64) {
+            Err(Error::LossyConversion { .. }) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(c, Value::Float64(1e300), ValueTy::Float32) {
+            Err(Error::LossyConversion { .. }) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn int_float() {
+        match coerce(Coercion::strict(), Value::Int64(1), ValueTy::Float32) {
+            Err(Error::WrongType { .. }) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(Coercion::default(), Value::Int64(1), ValueTy::Float32) {
+            Ok(Value::Float32(f)) => assert_eq!(f, 1.0),
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(Coercion::default(), Value::Int64(16_777_217), ValueTy::Float32) {
+            Err(Error::LossyConversion { .. }) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(Coercion::default(), Value::Float64(2.0), ValueTy::Int32) {
+            Ok(Value::Int32(2)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(Coercion::default(), Value::Float64(2.5), ValueTy::Int32) {
+            Err(Error::LossyConversion { .. }) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(Coercion::lenient(), Value::Float64(-2.5), ValueTy::Int32) {
+            Ok(Value::Int32(-2)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn non_numbers() {
+        match coerce(Coercion::default(), Value::Bool(true), ValueTy::Int32) {
+            Ok(Value::Bool(true)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+        match coerce(Coercion::default(), Value::Int64(4), ValueTy::String) {
+            Ok(Value::Int64(4)) => {}
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+}
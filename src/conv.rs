@@ -1,6 +1,12 @@
-use std::collections::VecDeque;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error;
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, RwLock};
 
-use {type_name_of, Any, Error, Result, Str, Value, ValueTy};
+use {type_name_of, Any, Coercion, Error, Function, Result, Shared, Str, Value, ValueTy};
 
 pub type MultiVal<'a> = VecDeque<Value<'a>>;
 
@@ -10,6 +16,12 @@ pub trait FromMultiValue<'a>: Sized {
     fn from(v: MultiVal<'a>) -> Result<Self>;
 }
 
+pub trait IntoMultiValue: Sized {
+    fn multi_in_ty() -> Vec<ValueTy>;
+
+    fn into_multi(self) -> Result<Vec<Value<'static>>>;
+}
+
 pub trait FromValue<'a>: Sized {
     fn out_ty() -> ValueTy;
 
@@ -42,16 +54,36 @@ impl<'a> FromValue<'a> for $fty {
     };
 }
 
-def_from_val!(i8, Int8);
-def_from_val!(i16, Int16);
-def_from_val!(i32, Int32);
-def_from_val!(i64, Int64);
-def_from_val!(u8, Uint8);
-def_from_val!(u16, Uint16);
-def_from_val!(u32, Uint32);
-def_from_val!(u64, Uint64);
-def_from_val!(f32, Float32);
-def_from_val!(f64, Float64);
+macro_rules! def_from_num {
+    ($fty:ty, $vty:ident) => {
+impl<'a> FromValue<'a> for $fty {
+    fn out_ty() -> ValueTy {
+        ValueTy::$vty
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match Coercion::default().coerce(v.into_res()?, &Self::out_ty())? {
+            Value::$vty(mat_val) => Ok(mat_val),
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+    };
+}
+
+def_from_num!(i8, Int8);
+def_from_num!(i16, Int16);
+def_from_num!(i32, Int32);
+def_from_num!(i64, Int64);
+def_from_num!(u8, Uint8);
+def_from_num!(u16, Uint16);
+def_from_num!(u32, Uint32);
+def_from_num!(u64, Uint64);
+def_from_num!(f32, Float32);
+def_from_num!(f64, Float64);
 def_from_val!(bool, Bool);
 def_from_val!(String, String, String, |s: Str| Ok(s.into_owned()));
 
@@ -60,7 +92,7 @@ where
     T: FromValue<'a>
 {
     fn out_ty() -> ValueTy {
-        T::out_ty()
+        ValueTy::Option(Box::new(T::out_ty()))
     }
 
     fn from(v: Value<'a>) -> Result<Self> {
@@ -71,6 +103,134 @@ where
     }
 }
 
+impl<'a, T> FromValue<'a> for Vec<T>
+where
+    T: FromValue<'a>
+{
+    fn out_ty() -> ValueTy {
+        ValueTy::Array(Box::new(T::out_ty()))
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match v.into_res()? {
+            Value::Array(a) => a.into_iter()
+                .enumerate()
+                .map(|(index, elem)| T::from(elem).map_err(|e| wrong_element(index, e)))
+                .collect(),
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+
+impl<'a, K, V, S> FromValue<'a> for HashMap<K, V, S>
+where
+    K: FromValue<'a> + Eq + Hash,
+    V: FromValue<'a>,
+    S: BuildHasher + Default,
+{
+    fn out_ty() -> ValueTy {
+        ValueTy::Map(Box::new(K::out_ty()), Box::new(V::out_ty()))
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match v.into_res()? {
+            Value::Map(m) => m.into_iter().map(from_entry).collect(),
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+
+impl<'a, K, V> FromValue<'a> for BTreeMap<K, V>
+where
+    K: FromValue<'a> + Ord,
+    V: FromValue<'a>,
+{
+    fn out_ty() -> ValueTy {
+        ValueTy::Map(Box::new(K::out_ty()), Box::new(V::out_ty()))
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match v.into_res()? {
+            Value::Map(m) => m.into_iter().map(from_entry).collect(),
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+
+fn from_entry<'a, K, V>((k, v): (Value<'a>, Value<'a>)) -> Result<(K, V)>
+where
+    K: FromValue<'a>,
+    V: FromValue<'a>,
+{
+    let key = entry_key(&k);
+    let wrong_entry = |error| Error::WrongEntry {
+        key: key.clone(),
+        error: Box::new(error),
+    };
+
+    Ok((K::from(k).map_err(&wrong_entry)?, V::from(v).map_err(&wrong_entry)?))
+}
+
+pub(crate) fn entry_key(k: &Value) -> Str {
+    match *k {
+        Value::String(ref s) => s.clone(),
+        ref other => Str::from(format!("{:?}", other)),
+    }
+}
+
+pub(crate) fn wrong_element(index: usize, error: Error) -> Error {
+    Error::WrongElement {
+        index,
+        error: Box::new(error),
+    }
+}
+
+impl<'a, T> FromValue<'a> for Box<T>
+where
+    T: Any,
+{
+    fn out_ty() -> ValueTy {
+        ValueTy::Custom
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match v.into_res()? {
+            Value::Custom(b) => {
+                let ty_name = b.as_ref().type_name();
+                b.downcast().map_err(|_| Error::WrongAny {
+                    expected: type_name_of::<T>(),
+                    found: ty_name,
+                })
+            }
+            Value::CustomSlot(slot) => match slot.take() {
+                Some(b) => b.downcast().map_err(|b| {
+                    let found = b.as_ref().type_name();
+                    *slot = Some(b);
+
+                    Error::WrongAny {
+                        expected: type_name_of::<T>(),
+                        found,
+                    }
+                }),
+                None => Err(Error::ValueMoved),
+            },
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+
 impl<'a, T> FromValue<'a> for &'a T
 where
     T: Any,
@@ -107,7 +267,7 @@ where
                 let ty_name = (&*r).type_name();
                 r.downcast_mut().ok_or(Error::WrongAny {
                     expected: type_name_of::<T>(),
-                    found: ty_name.into(),
+                    found: ty_name,
                 })
             }
             other => Err(Error::WrongType {
@@ -150,6 +310,87 @@ def_into!(u8, Uint8);
 def_into!(u16, Uint16);
 def_into!(u32, Uint32);
 def_into!(u64, Uint64);
+def_into!(f32, Float32);
+def_into!(f64, Float64);
+def_into!(bool, Bool);
+def_into!(String, String, |this: String| Ok(Value::String(Str::from(this))));
+def_into!(&'static str, String, |this: &'static str| Ok(Value::String(Str::from(this))));
+
+def_into!(Str, String, |this: Str| Ok(Value::String(this)));
+
+impl<K, V, S> IntoValue for HashMap<K, V, S>
+where
+    K: IntoValue + Eq + Hash,
+    V: IntoValue,
+    S: BuildHasher,
+{
+    fn in_ty() -> ValueTy {
+        ValueTy::Map(Box::new(K::in_ty()), Box::new(V::in_ty()))
+    }
+
+    fn into(self) -> Result<Value<'static>> {
+        into_map(self)
+    }
+}
+
+impl<K, V> IntoValue for BTreeMap<K, V>
+where
+    K: IntoValue + Ord,
+    V: IntoValue,
+{
+    fn in_ty() -> ValueTy {
+        ValueTy::Map(Box::new(K::in_ty()), Box::new(V::in_ty()))
+    }
+
+    fn into(self) -> Result<Value<'static>> {
+        into_map(self)
+    }
+}
+
+fn into_map<I, K, V>(entries: I) -> Result<Value<'static>>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: IntoValue,
+    V: IntoValue,
+{
+    entries
+        .into_iter()
+        .map(|(k, v)| Ok((K::into(k)?, V::into(v)?)))
+        .collect::<Result<_>>()
+        .map(Value::Map)
+}
+
+impl<T> IntoValue for Option<T>
+where
+    T: IntoValue
+{
+    fn in_ty() -> ValueTy {
+        ValueTy::Option(Box::new(T::in_ty()))
+    }
+
+    fn into(self) -> Result<Value<'static>> {
+        match self {
+            Some(val) => T::into(val),
+            None => Ok(Value::Nil),
+        }
+    }
+}
+
+impl<T> IntoValue for Vec<T>
+where
+    T: IntoValue
+{
+    fn in_ty() -> ValueTy {
+        ValueTy::Array(Box::new(T::in_ty()))
+    }
+
+    fn into(self) -> Result<Value<'static>> {
+        self.into_iter()
+            .map(T::into)
+            .collect::<Result<_>>()
+            .map(Value::Array)
+    }
+}
 
 impl<T> IntoValue for Box<T>
 where
@@ -164,11 +405,265 @@ where
     }
 }
 
+impl<T, E> IntoValue for ::std::result::Result<T, E>
+where
+    T: IntoValue,
+    E: error::Error + 'static,
+{
+    fn in_ty() -> ValueTy {
+        T::in_ty()
+    }
+
+    fn into(self) -> Result<Value<'static>> {
+        match self {
+            Ok(val) => T::into(val),
+            Err(e) => Err(Error::host(&e)),
+        }
+    }
+}
+
+impl IntoValue for Function {
+    fn in_ty() -> ValueTy {
+        ValueTy::Function(vec![], Box::new(ValueTy::Unknown))
+    }
+
+    fn into(self) -> Result<Value<'static>> {
+        Ok(Value::Function(self))
+    }
+}
+
+impl<F, V> IntoValue for Box<Fn(F) -> V>
+where
+    F: for<'a> FromMultiValue<'a> + 'static,
+    V: IntoValue + 'static,
+{
+    fn in_ty() -> ValueTy {
+        ValueTy::Function(F::multi_ty(), Box::new(V::in_ty()))
+    }
+
+    fn into(self) -> Result<Value<'static>> {
+        Ok(Value::Function(Function::new("<closure>", move |args| self(args))))
+    }
+}
+
+macro_rules! def_shared {
+    ($handle:ty) => {
+        impl<T> IntoValue for $handle
+        where
+            T: Any,
+        {
+            fn in_ty() -> ValueTy {
+                ValueTy::Shared
+            }
+
+            fn into(self) -> Result<Value<'static>> {
+                Ok(Value::Shared(Box::new(self) as Box<Shared>))
+            }
+        }
+
+        impl<'a, T> FromValue<'a> for $handle
+        where
+            T: Any,
+        {
+            fn out_ty() -> ValueTy {
+                ValueTy::Shared
+            }
+
+            fn from(v: Value<'a>) -> Result<Self> {
+                match v.into_res()? {
+                    Value::Shared(s) => s.as_any()
+                        .downcast_ref::<Self>()
+                        .cloned()
+                        .ok_or_else(|| Error::WrongAny {
+                            expected: type_name_of::<Self>(),
+                            found: s.as_any().type_name(),
+                        }),
+                    other => Err(Error::WrongType {
+                        expected: Self::out_ty(),
+                        found: other.ty(),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+def_shared!(Rc<RefCell<T>>);
+def_shared!(Arc<RwLock<T>>);
+def_shared!(Arc<Mutex<T>>);
+
+macro_rules! def_into_tuple {
+    ($($params:ident),*) => {
+        impl< $($params),* > IntoValue for ( $($params ,)* )
+        where
+            $( $params : IntoValue),*
+        {
+            fn in_ty() -> ValueTy {
+                ValueTy::Tuple(vec![ $( <$params as IntoValue>::in_ty() ),* ])
+            }
+
+            #[allow(non_snake_case)]
+            fn into(self) -> Result<Value<'static>> {
+                let ( $($params ,)* ) = self;
+
+                Ok(Value::Tuple(vec![ $( <$params as IntoValue>::into($params)? ),* ]))
+            }
+        }
+
+        def_into_tuple!(@ $($params),*);
+    };
+    (@ $head:ident) => {};
+    (@ $head:ident $(,$tail:ident)*) => {
+        def_into_tuple!($($tail),*);
+    };
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+def_into_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
 macro_rules! count_args {
     () => {0u16};
     ($head:ident $($tail:ident)*) => {1u16 + count_args!($($tail)*)};
 }
 
+impl<'a> FromValue<'a> for () {
+    fn out_ty() -> ValueTy {
+        ValueTy::Void
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match v.into_res()? {
+            Value::Void | Value::Nil => Ok(()),
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+
+macro_rules! def_from_tuple {
+    ($($params:ident),*) => {
+        impl< 'a, $($params),* > FromValue<'a> for ( $($params ,)* )
+        where
+            $( $params : FromValue<'a>),*
+        {
+            fn out_ty() -> ValueTy {
+                ValueTy::Tuple(vec![ $( <$params as FromValue<'a>>::out_ty() ),* ])
+            }
+
+            fn from(v: Value<'a>) -> Result<Self> {
+                let elems = match v.into_res()? {
+                    Value::Tuple(t) | Value::Array(t) => t,
+                    other => return Err(Error::WrongType {
+                        expected: Self::out_ty(),
+                        found: other.ty(),
+                    }),
+                };
+
+                let expected = count_args!($($params)*) as usize;
+                if elems.len() != expected {
+                    return Err(Error::WrongLength {
+                        expected,
+                        found: elems.len(),
+                    });
+                }
+
+                let mut elems = elems.into_iter().enumerate();
+
+                Ok(( $( {
+                    let (index, elem) = elems.next().unwrap();
+                    <$params as FromValue<'a>>::from(elem).map_err(|e| wrong_element(index, e))?
+                } ,)* ))
+            }
+        }
+
+        def_from_tuple!(@ $($params),*);
+    };
+    (@ $head:ident) => {};
+    (@ $head:ident $(,$tail:ident)*) => {
+        def_from_tuple!($($tail),*);
+    };
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+def_from_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+macro_rules! def_from_array {
+    ($($elems:ident),*) => {
+        impl<'a, T> FromValue<'a> for [T; count_args!($($elems)*) as usize]
+        where
+            T: FromValue<'a>
+        {
+            fn out_ty() -> ValueTy {
+                <Vec<T> as FromValue<'a>>::out_ty()
+            }
+
+            #[allow(non_snake_case)]
+            fn from(v: Value<'a>) -> Result<Self> {
+                let v = <Vec<T> as FromValue<'a>>::from(v)?;
+                let found = v.len();
+
+                let mut elems = v.into_iter();
+                $( let $elems = elems.next(); )*
+                match ($($elems,)* elems.next()) {
+                    ($(Some($elems),)* None) => Ok([$($elems),*]),
+                    _ => Err(Error::WrongLength {
+                        expected: count_args!($($elems)*) as usize,
+                        found,
+                    }),
+                }
+            }
+        }
+
+        def_from_array!(@ $($elems),*);
+    };
+    (@ $head:ident) => {};
+    (@ $head:ident $(,$tail:ident)*) => {
+        def_from_array!($($tail),*);
+    };
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+def_from_array!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+
+pub(crate) fn required(tys: &[ValueTy]) -> usize {
+    let optional = tys
+        .iter()
+        .rev()
+        .take_while(|ty| match **ty {
+            ValueTy::Option(_) | ValueTy::Rest(_) => true,
+            _ => false,
+        })
+        .count();
+
+    tys.len() - optional
+}
+
+fn take_arg<'a, T>(v: &mut MultiVal<'a>, index: usize) -> Result<T>
+where
+    T: FromValue<'a>,
+{
+    let val = mem::replace(&mut v[index], Value::Void);
+
+    T::from(val).map_err(|error| match error {
+        Error::Argument {
+            index: rest,
+            name,
+            error,
+        } => Error::Argument {
+            index: index + rest,
+            name,
+            error,
+        },
+        error => Error::Argument {
+            index,
+            name: None,
+            error: Box::new(error),
+        },
+    })
+}
+
 macro_rules! def_from_multi {
     ($($params:ident),*) => {
         impl< 'a, $($params),* > FromMultiValue<'a> for ( $($params ,)* )
@@ -179,18 +674,53 @@ macro_rules! def_from_multi {
                 vec![ $( <$params as FromValue<'a>>::out_ty() ),* ]
             }
 
-            #[allow(unused_mut)]
+            #[allow(unused_mut, unused_variables, unused_assignments, non_snake_case)]
             fn from(mut v: MultiVal<'a>) -> Result<Self> {
+                let tys = Self::multi_ty();
                 let len = v.len() as u16;
                 let expected = count_args!($($params)*);
-                if len != expected {
+                let variadic = match tys.last() {
+                    Some(&ValueTy::Rest(_)) => true,
+                    _ => false,
+                };
+                let required = required(&tys) as u16;
+                if len < required || (len > expected && !variadic) {
                     return Err(Error::WrongArgsNumber {
-                        expected,
+                        expected: if len < required { required } else { expected },
                         found: len,
                     });
                 }
 
-                Ok(( $( <$params as FromValue<'a>>::from(v.pop_front().unwrap())? ,)* ))
+                if variadic {
+                    while (v.len() as u16) < expected - 1 {
+                        v.push_back(Value::Nil);
+                    }
+                    let rest = v.split_off(expected as usize - 1).into_iter().collect();
+                    v.push_back(Value::Tuple(rest));
+                }
+                while (v.len() as u16) < expected {
+                    v.push_back(Value::Nil);
+                }
+
+                // Slots are claimed last so a failing argument leaves them untouched.
+                let mut index = 0;
+                $(
+                    let $params = match v[index] {
+                        Value::CustomSlot(_) => None,
+                        _ => Some(take_arg::<$params>(&mut v, index)?),
+                    };
+                    index += 1;
+                )*
+                let mut index = 0;
+                $(
+                    let $params = match $params {
+                        Some(arg) => arg,
+                        None => take_arg::<$params>(&mut v, index)?,
+                    };
+                    index += 1;
+                )*
+
+                Ok(( $( $params ,)* ))
             }
         }
 
@@ -204,3 +734,379 @@ macro_rules! def_from_multi {
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
 def_from_multi!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z);
+
+macro_rules! def_into_multi {
+    ($($params:ident),*) => {
+        impl< $($params),* > IntoMultiValue for ( $($params ,)* )
+        where
+            $( $params : IntoValue),*
+        {
+            fn multi_in_ty() -> Vec<ValueTy> {
+                vec![ $( <$params as IntoValue>::in_ty() ),* ]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_multi(self) -> Result<Vec<Value<'static>>> {
+                let ( $($params ,)* ) = self;
+
+                Ok(vec![ $( <$params as IntoValue>::into($params)? ),* ])
+            }
+        }
+
+        def_into_multi!(@ $($params),*);
+    };
+    (@) => {};
+    (@ $head:ident $(,$tail:ident)*) => {
+        def_into_multi!($($tail),*);
+    };
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+def_into_multi!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests {
+    use fnv::FnvHashMap;
+
+    use super::*;
+
+    fn round_trip<T>(val: T) -> T
+    where
+        T: IntoValue + for<'a> FromValue<'a>,
+    {
+        <T as FromValue>::from(IntoValue::into(val).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn into_scalars() {
+        assert_eq!(round_trip(true), true);
+        assert_eq!(round_trip(1.5f32), 1.5);
+        assert_eq!(round_trip(-2.25f64), -2.25);
+        assert_eq!(round_trip("foo".to_owned()), "foo");
+
+        let s = <String as FromValue>::from(IntoValue::into("bar").unwrap()).unwrap();
+        assert_eq!(s, "bar");
+    }
+
+    #[test]
+    fn into_option() {
+        assert_eq!(round_trip(Some(5i32)), Some(5));
+        assert_eq!(round_trip(None::<i32>), None);
+    }
+
+    #[test]
+    fn into_vec() {
+        let val = IntoValue::into(vec![1u8, 2, 3]).unwrap();
+        match val {
+            Value::Array(a) => {
+                let a: Vec<u8> = a.into_iter()
+                    .map(|v| <u8 as FromValue>::from(v).unwrap())
+                    .collect();
+                assert_eq!(a, vec![1, 2, 3]);
+            }
+            other => panic!("Expected array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn into_tuple() {
+        let val = IntoValue::into((7i64, "seven", false)).unwrap();
+        match val {
+            Value::Tuple(t) => {
+                let mut t = t.into_iter();
+                assert_eq!(<i64 as FromValue>::from(t.next().unwrap()).unwrap(), 7);
+                assert_eq!(<String as FromValue>::from(t.next().unwrap()).unwrap(), "seven");
+                assert_eq!(<bool as FromValue>::from(t.next().unwrap()).unwrap(), false);
+                assert!(t.next().is_none());
+            }
+            other => panic!("Expected tuple, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_coerced() {
+        assert_eq!(<i32 as FromValue>::from(Value::Int64(-5)).unwrap(), -5);
+        assert_eq!(<f32 as FromValue>::from(Value::Int64(3)).unwrap(), 3.0);
+        assert_eq!(<u8 as FromValue>::from(Value::Float64(4.0)).unwrap(), 4);
+
+        match <u8 as FromValue>::from(Value::Int64(256)) {
+            Err(Error::LossyConversion { .. }) => {}
+            other => panic!("Expected lossy conversion, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_box() {
+        #[derive(Debug, PartialEq)]
+        struct Foo(i32);
+        struct Bar;
+
+        assert_eq!(round_trip(Box::new(Foo(5))), Box::new(Foo(5)));
+
+        let val = IntoValue::into(Box::new(Bar)).unwrap();
+        match <Box<Foo> as FromValue>::from(val) {
+            Err(Error::WrongAny { found, .. }) => assert!(found.ends_with("Bar")),
+            other => panic!("Expected wrong any, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_slot() {
+        #[derive(Debug, PartialEq)]
+        struct Foo(i32);
+        struct Bar;
+
+        let mut slot: Option<Box<Any>> = Some(Box::new(Bar));
+        assert!(<Box<Foo> as FromValue>::from(Value::CustomSlot(&mut slot)).is_err());
+        assert!(slot.is_some());
+
+        let mut slot: Option<Box<Any>> = Some(Box::new(Foo(5)));
+        let args = vec![Value::CustomSlot(&mut slot), Value::Bool(true)];
+        assert!(<(Box<Foo>, i32) as FromMultiValue>::from(args.into()).is_err());
+        assert!(slot.is_some());
+
+        let args = vec![Value::CustomSlot(&mut slot), Value::Int32(2)];
+        let (foo, _) = <(Box<Foo>, i32) as FromMultiValue>::from(args.into()).unwrap();
+        assert_eq!(foo, Box::new(Foo(5)));
+        assert!(slot.is_none());
+    }
+
+    #[test]
+    fn from_shared() {
+        let rc = Rc::new(RefCell::new(5u32));
+        let val = IntoValue::into(rc.clone()).unwrap();
+        assert_eq!(val.ty(), ValueTy::Shared);
+
+        let back = <Rc<RefCell<u32>> as FromValue>::from(val).unwrap();
+        *back.borrow_mut() = 6;
+        assert_eq!(*rc.borrow(), 6);
+
+        let val = IntoValue::into(Arc::new(RwLock::new(5u32))).unwrap();
+        match <Rc<RefCell<u32>> as FromValue>::from(val) {
+            Err(Error::WrongAny { .. }) => {}
+            other => panic!("Expected wrong any, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_map() {
+        let mut config = HashMap::new();
+        config.insert("gravity".to_owned(), 9.81f32);
+        config.insert("speed".to_owned(), 2.0);
+        assert_eq!(round_trip(config.clone()), config);
+
+        let mut names = BTreeMap::new();
+        names.insert(1i32, "one".to_owned());
+        names.insert(2, "two".to_owned());
+        assert_eq!(round_trip(names.clone()), names);
+
+        let mut map: FnvHashMap<String, _> = FnvHashMap::default();
+        map.insert("a".to_owned(), vec![1u8]);
+        assert_eq!(round_trip(map.clone()), map);
+
+        let mut map = ::Map::default();
+        map.insert(Str::from("a"), 1u8);
+        let val = IntoValue::into(map).unwrap();
+        assert_eq!(val, Value::Map(vec![(Value::String(Str::from("a")), Value::Uint8(1))]));
+
+        let val = Value::Map(vec![(Value::String(Str::from("a")), Value::Bool(true))]);
+        match <HashMap<String, i32> as FromValue>::from(val) {
+            Err(Error::WrongEntry { ref key, .. }) if key == "a" => {}
+            other => panic!("Expected entry error, got {:?}", other),
+        }
+
+        assert_ty::<BTreeMap<String, Vec<f64>>>(ValueTy::Map(
+            Box::new(ValueTy::String),
+            Box::new(ValueTy::Array(Box::new(ValueTy::Float64))),
+        ));
+    }
+
+    #[test]
+    fn into_result() {
+        use std::fmt;
+        use std::num::ParseIntError;
+
+        #[derive(Debug)]
+        struct ConfigError(ParseIntError);
+
+        impl fmt::Display for ConfigError {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "invalid config")
+            }
+        }
+
+        impl error::Error for ConfigError {
+            fn source(&self) -> Option<&(error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let parse = |s: &str| s.parse::<i32>().map_err(ConfigError);
+
+        assert_eq!(
+            <::std::result::Result<i32, ConfigError> as IntoValue>::in_ty(),
+            ValueTy::Int32
+        );
+        assert_eq!(IntoValue::into(parse("5")).unwrap(), Value::Int32(5));
+
+        match IntoValue::into(parse("five")) {
+            Err(Error::Host { message, sources }) => {
+                assert_eq!(message, "invalid config");
+                assert_eq!(sources, vec![Str::from("invalid digit found in string")]);
+            }
+            other => panic!("Expected host error, got {:?}", other),
+        }
+
+        let res: Result<i32> = Err(Error::MissingSelfArg);
+        match IntoValue::into(res) {
+            Err(Error::MissingSelfArg) => {}
+            other => panic!("Expected original error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_vec() {
+        assert_eq!(round_trip(vec![1i32, 2, 3]), vec![1, 2, 3]);
+        assert_eq!(round_trip(Vec::<String>::new()), Vec::<String>::new());
+        assert_eq!(
+            round_trip(vec![vec![1u16], vec![], vec![2, 3]]),
+            vec![vec![1], vec![], vec![2, 3]]
+        );
+    }
+
+    #[test]
+    fn from_array() {
+        let val = IntoValue::into(vec![1.0f64, 2.0]).unwrap();
+        assert_eq!(<[f64; 2] as FromValue>::from(val).unwrap(), [1.0, 2.0]);
+
+        let val = IntoValue::into(vec![1.0f64, 2.0]).unwrap();
+        match <[f64; 3] as FromValue>::from(val) {
+            Err(Error::WrongLength { expected: 3, found: 2 }) => {}
+            other => panic!("Expected length error, got {:?}", other),
+        }
+
+        let val = IntoValue::into(vec![1u8; 17]).unwrap();
+        match <[u8; 16] as FromValue>::from(val) {
+            Err(Error::WrongLength { expected: 16, found: 17 }) => {}
+            other => panic!("Expected length error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_tuple() {
+        assert_eq!(round_trip((1u8, "a".to_owned())), (1, "a".to_owned()));
+        assert_eq!(
+            round_trip(vec![(1i32, 2.5f32), (3, 4.5)]),
+            vec![(1, 2.5), (3, 4.5)]
+        );
+
+        let val = Value::Array(vec![Value::Int32(1), Value::Bool(true)]);
+        assert_eq!(<(i32, bool) as FromValue>::from(val).unwrap(), (1, true));
+    }
+
+    #[test]
+    fn wrong_element() {
+        let val = Value::Array(vec![
+            Value::Array(vec![Value::Int32(1)]),
+            Value::Array(vec![Value::Int32(2), Value::Bool(false)]),
+        ]);
+
+        match <Vec<Vec<i32>> as FromValue>::from(val) {
+            Err(Error::WrongElement { index: 1, error }) => match *error {
+                Error::WrongElement { index: 1, error } => match *error {
+                    Error::WrongType { .. } => {}
+                    other => panic!("Expected type error, got {:?}", other),
+                },
+                other => panic!("Expected element error, got {:?}", other),
+            },
+            other => panic!("Expected element error, got {:?}", other),
+        }
+
+        let val = Value::Tuple(vec![Value::Int32(1), Value::Int32(2)]);
+        match <(i32, String) as FromValue>::from(val) {
+            Err(Error::WrongElement { index: 1, .. }) => {}
+            other => panic!("Expected element error, got {:?}", other),
+        }
+    }
+
+    fn ty_of<T>() -> (ValueTy, ValueTy)
+    where
+        T: IntoValue + for<'a> FromValue<'a>,
+    {
+        (<T as IntoValue>::in_ty(), <T as FromValue>::out_ty())
+    }
+
+    fn assert_ty<T>(expected: ValueTy)
+    where
+        T: IntoValue + for<'a> FromValue<'a>,
+    {
+        assert_eq!(ty_of::<T>(), (expected.clone(), expected));
+    }
+
+    #[test]
+    fn scalar_ty() {
+        assert_ty::<bool>(ValueTy::Bool);
+        assert_ty::<i8>(ValueTy::Int8);
+        assert_ty::<i16>(ValueTy::Int16);
+        assert_ty::<i32>(ValueTy::Int32);
+        assert_ty::<i64>(ValueTy::Int64);
+        assert_ty::<u8>(ValueTy::Uint8);
+        assert_ty::<u16>(ValueTy::Uint16);
+        assert_ty::<u32>(ValueTy::Uint32);
+        assert_ty::<u64>(ValueTy::Uint64);
+        assert_ty::<f32>(ValueTy::Float32);
+        assert_ty::<f64>(ValueTy::Float64);
+        assert_ty::<String>(ValueTy::String);
+
+        assert_eq!(<&'static str as IntoValue>::in_ty(), ValueTy::String);
+        assert_eq!(<() as IntoValue>::in_ty(), ValueTy::Void);
+    }
+
+    #[test]
+    fn structural_ty() {
+        assert_ty::<Option<i32>>(ValueTy::Option(Box::new(ValueTy::Int32)));
+        assert_ty::<Vec<f32>>(ValueTy::Array(Box::new(ValueTy::Float32)));
+        assert_ty::<Vec<Vec<u8>>>(ValueTy::Array(Box::new(ValueTy::Array(Box::new(
+            ValueTy::Uint8,
+        )))));
+        assert_ty::<(bool, String)>(ValueTy::Tuple(vec![ValueTy::Bool, ValueTy::String]));
+        assert_ty::<Option<Vec<(f32, String)>>>(ValueTy::Option(Box::new(ValueTy::Array(
+            Box::new(ValueTy::Tuple(vec![ValueTy::Float32, ValueTy::String])),
+        ))));
+
+        assert_eq!(
+            <[i64; 4] as FromValue>::out_ty(),
+            ValueTy::Array(Box::new(ValueTy::Int64))
+        );
+    }
+
+    #[test]
+    fn custom_ty() {
+        struct Foo;
+
+        assert_eq!(<Box<Foo> as IntoValue>::in_ty(), ValueTy::Custom);
+        assert_eq!(<&Foo as FromValue>::out_ty(), ValueTy::CustomRef);
+        assert_eq!(<&mut Foo as FromValue>::out_ty(), ValueTy::CustomMut);
+        assert_eq!(
+            <Option<&Foo> as FromValue>::out_ty(),
+            ValueTy::Option(Box::new(ValueTy::CustomRef))
+        );
+    }
+
+    #[test]
+    fn value_ty() {
+        assert_eq!(
+            Value::Array(vec![Value::Int32(1)]).ty(),
+            ValueTy::Array(Box::new(ValueTy::Int32))
+        );
+        assert_eq!(
+            Value::Array(vec![]).ty(),
+            ValueTy::Array(Box::new(ValueTy::Unknown))
+        );
+        assert_eq!(Value::Nil.ty(), ValueTy::Option(Box::new(ValueTy::Unknown)));
+        assert_eq!(
+            Value::Tuple(vec![Value::Bool(true), Value::String(Str::from("a"))]).ty(),
+            ValueTy::Tuple(vec![ValueTy::Bool, ValueTy::String])
+        );
+    }
+}
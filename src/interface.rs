@@ -0,0 +1,212 @@
+use {Any, Error, Result};
+
+pub trait Interface {
+    fn ident() -> &'static str;
+
+    fn cast(any: &Any) -> Option<&Self>;
+
+    fn cast_mut(any: &mut Any) -> Option<&mut Self>;
+}
+
+pub trait Implements<I: ?Sized> {}
+
+#[macro_export]
+macro_rules! interface {
+    ($iface:ident: $($ty:ty),+ $(,)*) => {
+        impl $crate::Interface for $iface {
+            fn ident() -> &'static str {
+                stringify!($iface)
+            }
+
+            fn cast(any: &$crate::Any) -> Option<&Self> {
+                $(
+                    if let Some(this) = any.downcast_ref::<$ty>() {
+                        return Some(this);
+                    }
+                )+
+
+                None
+            }
+
+            fn cast_mut(any: &mut $crate::Any) -> Option<&mut Self> {
+                $(
+                    if any.is::<$ty>() {
+                        return any.downcast_mut::<$ty>().map(|this| this as &mut Self);
+                    }
+                )+
+
+                None
+            }
+        }
+
+        $(
+            impl $crate::Implements<$iface> for $ty {}
+        )+
+
+        impl<'a> $crate::FromValue<'a> for &'a $iface {
+            fn out_ty() -> $crate::ValueTy {
+                $crate::ValueTy::CustomRef
+            }
+
+            fn from(v: $crate::Value<'a>) -> $crate::Result<Self> {
+                match v.into_res()? {
+                    $crate::Value::CustomRef(r) => {
+                        <$iface as $crate::Interface>::cast(r).ok_or_else(|| {
+                            $crate::Error::WrongAny {
+                                expected: stringify!($iface),
+                                found: $crate::Any::type_name(r),
+                            }
+                        })
+                    }
+                    other => Err($crate::Error::WrongType {
+                        expected: $crate::ValueTy::CustomRef,
+                        found: other.ty(),
+                    }),
+                }
+            }
+        }
+
+        impl<'a> $crate::FromValue<'a> for &'a mut $iface {
+            fn out_ty() -> $crate::ValueTy {
+                $crate::ValueTy::CustomMut
+            }
+
+            fn from(v: $crate::Value<'a>) -> $crate::Result<Self> {
+                match v.into_res()? {
+                    $crate::Value::CustomMut(r) => {
+                        let found = $crate::Any::type_name(&*r);
+                        let error = $crate::Error::WrongAny {
+                            expected: stringify!($iface),
+                            found,
+                        };
+
+                        <$iface as $crate::Interface>::cast_mut(r)
+                            .map(|this| this as &mut $iface)
+                            .ok_or(error)
+                    }
+                    other => Err($crate::Error::WrongType {
+                        expected: $crate::ValueTy::CustomMut,
+                        found: other.ty(),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) fn cast<I>(any: &Any) -> Result<&I>
+where
+    I: Interface + ?Sized,
+{
+    I::cast(any).ok_or_else(|| Error::WrongAny {
+        expected: I::ident(),
+        found: any.type_name(),
+    })
+}
+
+pub(crate) fn cast_mut<I>(any: &mut Any) -> Result<&mut I>
+where
+    I: Interface + ?Sized,
+{
+    let found = (&*any).type_name();
+
+    I::cast_mut(any).ok_or(Error::WrongAny {
+        expected: I::ident(),
+        found,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    use {Builder, FromValue, InterfaceBuilder, TyBuilder, Value};
+
+    trait Drawable {
+        fn draw(&self) -> String;
+
+        fn set_visible(&mut self, visible: bool);
+    }
+
+    struct Sprite(bool);
+
+    struct Text(&'static str);
+
+    struct Mesh;
+
+    impl Drawable for Sprite {
+        fn draw(&self) -> String {
+            format!("sprite {}", self.0)
+        }
+
+        fn set_visible(&mut self, visible: bool) {
+            self.0 = visible;
+        }
+    }
+
+    impl Drawable for Text {
+        fn draw(&self) -> String {
+            self.0.to_owned()
+        }
+
+        fn set_visible(&mut self, _: bool) {}
+    }
+
+    interface!(Drawable: Sprite, Text);
+
+    #[test]
+    fn shared_interface() {
+        let drawable = InterfaceBuilder::<Drawable>::new("Drawable")
+            .with_method("draw", |this, ()| this.draw())
+            .with_method_mut("set_visible", |this, (visible,): (bool,)| {
+                this.set_visible(visible)
+            });
+        let map = Builder::default()
+            .with_ty(TyBuilder::<Sprite>::new("Sprite").with_interface(&drawable))
+            .with_ty(TyBuilder::<Text>::new("Text").with_interface(&drawable))
+            .finish()
+            .unwrap();
+        let sprite = &map[&::std::any::TypeId::of::<Sprite>()];
+        let text = &map[&::std::any::TypeId::of::<Text>()];
+
+        assert_eq!(sprite.interfaces, vec!["Drawable"]);
+        let value = Rc::new(RefCell::new(Sprite(false)));
+        let shared = || Value::Shared(Box::new(value.clone()));
+        (sprite.methods[1].exec)(vec![shared(), Value::Bool(true)])
+            .into_res()
+            .unwrap();
+        assert_eq!(
+            (text.methods[0].exec)(vec![shared()]),
+            Value::String(::Str::from("sprite true"))
+        );
+
+        let hello = Text("hello");
+        let d = <&Drawable as FromValue>::from(Value::CustomRef(&hello)).unwrap();
+        assert_eq!(d.draw(), "hello");
+        match <&Drawable as FromValue>::from(Value::CustomRef(&Mesh)) {
+            Err(e) => assert!(e.to_string().contains("Drawable")),
+            Ok(_) => panic!("Mesh does not implement Drawable"),
+        }
+    }
+
+    #[test]
+    fn catch_unwind() {
+        let build = |catch_unwind| {
+            let drawable = InterfaceBuilder::<Drawable>::new("Drawable")
+                .with_catch_unwind(catch_unwind)
+                .with_method("draw", |_, ()| -> String { panic!("boom") });
+            TyBuilder::<Text>::new("Text").with_interface(&drawable).ty().methods[0].clone()
+        };
+        let text = Text("hello");
+
+        match (build(true).exec)(vec![Value::CustomRef(&text)]) {
+            Value::Error(ref e) => assert_eq!(e.to_string(), "Drawable.draw: panicked: boom"),
+            other => panic!("Expected panic error, got {:?}", other),
+        }
+        let draw = build(false);
+        let call = || (draw.exec)(vec![Value::CustomRef(&text)]);
+        assert!(panic::catch_unwind(AssertUnwindSafe(call)).is_err());
+    }
+}
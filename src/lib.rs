@@ -3,10 +3,21 @@
 extern crate fnv;
 
 pub use any::{type_name_of, Any};
-pub use builder::{Builder, PropertyBuilder, TyBuilder};
-pub use conv::{FromValue, FromMultiValue, IntoValue, MultiVal};
+pub use builder::{
+    Builder, EnumBuilder, Fields, InterfaceBuilder, Items, ModuleBuilder, PropertyBuilder,
+    TyBuilder,
+};
+pub use callback::Callback;
+pub use cmp::HashKey;
+pub use coerce::{Coercion, FloatToInt, IntToFloat};
+pub use conv::{FromValue, FromMultiValue, IntoMultiValue, IntoValue, MultiVal};
+pub use interface::{Implements, Interface};
+pub use overload::resolve;
+pub use rest::Rest;
+pub use shared::Shared;
+pub use sync::{Captures, Local, SyncTyMap, ThreadSafe};
 
-use std::any::TypeId;
+use std::any::{Any as StdAny, TypeId};
 use std::borrow::Cow;
 use std::error;
 use std::fmt;
@@ -14,11 +25,23 @@ use std::sync::Arc;
 
 mod any;
 mod builder;
+mod callback;
+mod cmp;
+mod coerce;
 mod conv;
+mod interface;
+mod overload;
+mod rest;
+mod shared;
+mod sync;
 
 #[derive(Clone, Debug)]
 pub enum Error {
     MissingSelfArg,
+    ValueMoved,
+    AlreadyBorrowed {
+        ty: &'static str,
+    },
     WrongArgsNumber {
         expected: u16,
         found: u16,
@@ -31,6 +54,94 @@ pub enum Error {
         expected: &'static str,
         found: &'static str,
     },
+    WrongLength {
+        expected: usize,
+        found: usize,
+    },
+    WrongElement {
+        index: usize,
+        error: Box<Error>,
+    },
+    WrongEntry {
+        key: Str,
+        error: Box<Error>,
+    },
+    NotCloneable {
+        ty: &'static str,
+    },
+    NotHashable {
+        found: ValueTy,
+    },
+    Script {
+        message: Str,
+    },
+    Host {
+        message: Str,
+        sources: Vec<Str>,
+    },
+    LossyConversion {
+        value: Str,
+        expected: ValueTy,
+    },
+    Argument {
+        index: usize,
+        name: Option<Str>,
+        error: Box<Error>,
+    },
+    Call {
+        ident: Str,
+        error: Box<Error>,
+    },
+    Panicked {
+        message: Str,
+    },
+    AmbiguousOverload {
+        ident: Str,
+        signatures: Vec<Vec<ValueTy>>,
+    },
+    NoOverload {
+        ident: Str,
+        found: Vec<ValueTy>,
+        candidates: Vec<Vec<ValueTy>>,
+    },
+    UnknownArgument {
+        name: Str,
+    },
+    MissingArgument {
+        name: Str,
+    },
+    DuplicateArgument {
+        name: Str,
+    },
+    MissingParent {
+        ident: Str,
+    },
+    ParentCycle {
+        ident: Str,
+    },
+}
+
+impl Error {
+    pub fn host<E>(err: &E) -> Self
+    where
+        E: error::Error + 'static,
+    {
+        if let Some(err) = (err as &StdAny).downcast_ref::<Error>() {
+            return err.clone();
+        }
+
+        let mut sources = vec![];
+        let mut source = err.source();
+        while let Some(err) = source {
+            sources.push(Str::from(err.to_string()));
+            source = err.source();
+        }
+
+        Error::Host {
+            message: Str::from(err.to_string()),
+            sources,
+        }
+    }
 }
 
 impl error::Error for Error {
@@ -41,22 +152,244 @@ impl error::Error for Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match *self {
+            Error::MissingSelfArg => write!(f, "missing self argument"),
+            Error::ValueMoved => write!(f, "value was moved"),
+            Error::AlreadyBorrowed { ty } => write!(f, "{} is already borrowed", ty),
+            Error::WrongArgsNumber { expected, found } => {
+                write!(f, "expected {} arguments, found {}", expected, found)
+            }
+            Error::WrongType {
+                ref expected,
+                ref found,
+            } => write!(f, "expected {:?}, found {:?}", expected, found),
+            Error::WrongAny { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            Error::WrongLength { expected, found } => {
+                write!(f, "expected {} elements, found {}", expected, found)
+            }
+            Error::WrongElement { index, ref error } => write!(f, "element {}: {}", index, error),
+            Error::WrongEntry { ref key, ref error } => write!(f, "entry {}: {}", key, error),
+            Error::NotCloneable { ty } => write!(f, "{} cannot be cloned", ty),
+            Error::NotHashable { ref found } => write!(f, "{:?} cannot be hashed", found),
+            Error::Script { ref message } => write!(f, "{}", message),
+            Error::Host {
+                ref message,
+                ref sources,
+            } => {
+                write!(f, "{}", message)?;
+                for source in sources {
+                    write!(f, "\ncaused by: {}", source)?;
+                }
+
+                Ok(())
+            }
+            Error::LossyConversion {
+                ref value,
+                ref expected,
+            } => write!(f, "cannot convert {} to {:?} without loss", value, expected),
+            Error::Argument {
+                index,
+                name: Some(ref name),
+                ref error,
+            } => write!(f, "argument {} ({}): {}", index, name, error),
+            Error::Argument {
+                index,
+                name: None,
+                ref error,
+            } => write!(f, "argument {}: {}", index, error),
+            Error::Call {
+                ref ident,
+                ref error,
+            } => write!(f, "{}: {}", ident, error),
+            Error::Panicked { ref message } => write!(f, "panicked: {}", message),
+            Error::AmbiguousOverload {
+                ref ident,
+                ref signatures,
+            } => {
+                let signatures: Vec<_> =
+                    signatures.iter().map(|s| overload::signature(s)).collect();
+
+                write!(f, "{}: ambiguous overloads {}", ident, signatures.join(", "))
+            }
+            Error::NoOverload {
+                ref ident,
+                ref found,
+                ref candidates,
+            } => {
+                let candidates: Vec<_> =
+                    candidates.iter().map(|s| overload::signature(s)).collect();
+
+                write!(
+                    f,
+                    "{}: no overload matches {}; candidates: {}",
+                    ident,
+                    overload::signature(found),
+                    candidates.join(", ")
+                )
+            }
+            Error::UnknownArgument { ref name } => write!(f, "unknown argument {}", name),
+            Error::MissingArgument { ref name } => write!(f, "missing argument {}", name),
+            Error::DuplicateArgument { ref name } => {
+                write!(f, "argument {} was passed more than once", name)
+            }
+            Error::MissingParent { ref ident } => {
+                write!(f, "the parent of {} has not been registered", ident)
+            }
+            Error::ParentCycle { ref ident } => write!(f, "{} inherits from itself", ident),
+        }
     }
 }
 
+pub type ClonePtr = Arc<Fn(&Any) -> Box<Any>>;
+
 #[derive(Clone)]
 pub struct Function {
     pub exec: FunPtr,
     pub ident: Str,
     pub args: Vec<ValueTy>,
+    pub arg_names: Vec<Str>,
+    pub defaults: Vec<DefaultPtr>,
     pub ret: ValueTy,
 }
 
+impl Function {
+    pub fn min_args(&self) -> usize {
+        conv::required(&self.args).min(self.args.len() - self.defaults.len())
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        match self.args.last() {
+            Some(&ValueTy::Rest(_)) => true,
+            _ => false,
+        }
+    }
+
+    // Bindings decide what counts as a keyword argument; gsf_rlua takes a trailing table
+    // with string keys, unless the parameter at that position itself accepts a table.
+    pub fn order_named<V, N, D>(
+        &self,
+        positional: Vec<V>,
+        named: Vec<(Str, V)>,
+        nil: N,
+        default: D,
+    ) -> Result<Vec<V>>
+    where
+        N: Fn() -> V,
+        D: Fn(Value<'static>) -> Result<V>,
+    {
+        let mut slots: Vec<Option<V>> = positional.into_iter().map(Some).collect();
+        for (name, val) in named {
+            let index = match self.arg_names.iter().position(|n| *n == name) {
+                Some(index) => index,
+                None => return Err(Error::UnknownArgument { name }),
+            };
+
+            while slots.len() <= index {
+                slots.push(None);
+            }
+            if slots[index].is_some() {
+                return Err(Error::DuplicateArgument { name });
+            }
+            slots[index] = Some(val);
+        }
+
+        let first_default = self.args.len() - self.defaults.len();
+        slots
+            .into_iter()
+            .enumerate()
+            .map(|(i, slot)| match (slot, self.args.get(i)) {
+                (Some(val), _) => Ok(val),
+                (None, _) if i >= first_default => {
+                    default(self.defaults[i - first_default]().into_res()?)
+                }
+                (None, Some(&ValueTy::Option(_))) => Ok(nil()),
+                (None, _) => Err(Error::MissingArgument {
+                    name: self.arg_names
+                        .get(i)
+                        .cloned()
+                        .unwrap_or_else(|| Str::from(i.to_string())),
+                }),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct Constant {
+    pub ident: Str,
+    pub ty: ValueTy,
+    pub value: DefaultPtr,
+}
+
+pub type DefaultPtr = Arc<Fn() -> Value<'static>>;
+
 pub type FunPtr = Arc<Fn(Vec<Value>) -> Value<'static>>;
 
 pub type Map<T> = fnv::FnvHashMap<Str, T>;
 
+#[derive(Clone)]
+pub struct Module {
+    pub constants: Vec<Constant>,
+    pub functions: Vec<Function>,
+    pub ident: Str,
+    pub modules: Vec<Module>,
+    pub types: Vec<TypeId>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Eq,
+    Lt,
+    Le,
+    Index,
+    NewIndex,
+    Len,
+    Concat,
+    Call,
+    ToString,
+}
+
+impl Operator {
+    pub fn ident(&self) -> &'static str {
+        match *self {
+            Operator::Add => "add",
+            Operator::Sub => "sub",
+            Operator::Mul => "mul",
+            Operator::Div => "div",
+            Operator::Neg => "neg",
+            Operator::Eq => "eq",
+            Operator::Lt => "lt",
+            Operator::Le => "le",
+            Operator::Index => "index",
+            Operator::NewIndex => "newindex",
+            Operator::Len => "len",
+            Operator::Concat => "concat",
+            Operator::Call => "call",
+            Operator::ToString => "tostring",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Parent {
+    pub id: TypeId,
+    pub ident: Str,
+    pub inherited: Vec<Str>,
+    pub project: ProjectPtr,
+    pub project_mut: ProjectMutPtr,
+}
+
+pub type ProjectMutPtr = Arc<Fn(&mut Any) -> &mut Any>;
+
+pub type ProjectPtr = Arc<Fn(&Any) -> &Any>;
+
 #[derive(Clone)]
 pub struct Property {
     pub ident: Str,
@@ -71,16 +404,35 @@ pub type Str = Cow<'static, str>;
 
 #[derive(Clone)]
 pub struct Ty {
+    pub clone: Option<ClonePtr>,
+    pub constants: Vec<Constant>,
     pub functions: Vec<Function>,
     pub id: TypeId,
     pub ident: Str,
+    pub interfaces: Vec<Str>,
     pub methods: Vec<Function>,
+    pub operators: Vec<(Operator, Function)>,
+    pub parent: Option<Parent>,
     pub properties: Vec<Property>,
+    pub variants: Vec<Variant>,
 }
 
 pub type TyMap = Arc<TyMapMut>;
 pub type TyMapMut = fnv::FnvHashMap<TypeId, Ty>;
 
+#[derive(Clone)]
+pub struct Variant {
+    pub discriminant: Option<i64>,
+    pub ident: Str,
+    pub kind: VariantKind,
+}
+
+#[derive(Clone)]
+pub enum VariantKind {
+    Unit(DefaultPtr),
+    Constructor(Function),
+}
+
 pub enum Value<'a> {
     Nil,
     Void,
@@ -99,8 +451,12 @@ pub enum Value<'a> {
     Custom(Box<Any>),
     CustomRef(&'a Any),
     CustomMut(&'a mut Any),
+    CustomSlot(&'a mut Option<Box<Any>>),
+    Shared(Box<Shared>),
     Array(Vec<Value<'a>>),
+    Map(Vec<(Value<'a>, Value<'a>)>),
     String(Str),
+    Function(Function),
     Error(Error),
 }
 
@@ -112,6 +468,108 @@ impl<'a> Value<'a> {
     pub fn ty(&self) -> ValueTy {
         ValueTy::from(self)
     }
+
+    pub fn try_clone(&self, map: &TyMapMut) -> Result<Self> {
+        let val = match *self {
+            Value::Nil => Value::Nil,
+            Value::Void => Value::Void,
+            Value::Tuple(ref v) => Value::Tuple(try_clone_all(v, map)?),
+            Value::Bool(b) => Value::Bool(b),
+            Value::Int8(i) => Value::Int8(i),
+            Value::Int16(i) => Value::Int16(i),
+            Value::Int32(i) => Value::Int32(i),
+            Value::Int64(i) => Value::Int64(i),
+            Value::Uint8(i) => Value::Uint8(i),
+            Value::Uint16(i) => Value::Uint16(i),
+            Value::Uint32(i) => Value::Uint32(i),
+            Value::Uint64(i) => Value::Uint64(i),
+            Value::Float32(f) => Value::Float32(f),
+            Value::Float64(f) => Value::Float64(f),
+            Value::Custom(ref c) => {
+                let id = unsafe { Any::__type_id(&**c) };
+                let clone = map.get(&id)
+                    .and_then(|ty| ty.clone.as_ref())
+                    .ok_or_else(|| Error::NotCloneable {
+                        ty: c.as_ref().type_name(),
+                    })?;
+
+                Value::Custom(clone(c.as_ref()))
+            }
+            Value::CustomRef(r) => Value::CustomRef(r),
+            Value::CustomMut(ref r) => {
+                return Err(Error::NotCloneable {
+                    ty: (&**r).type_name(),
+                })
+            }
+            Value::CustomSlot(ref s) => match **s {
+                Some(ref c) => {
+                    return Err(Error::NotCloneable {
+                        ty: c.as_ref().type_name(),
+                    })
+                }
+                None => return Err(Error::ValueMoved),
+            },
+            Value::Shared(ref s) => Value::Shared(s.share()),
+            Value::Array(ref v) => Value::Array(try_clone_all(v, map)?),
+            Value::Map(ref m) => Value::Map(m.iter()
+                .map(|&(ref k, ref v)| Ok((k.try_clone(map)?, v.try_clone(map)?)))
+                .collect::<Result<_>>()?),
+            Value::String(ref s) => Value::String(s.clone()),
+            Value::Function(ref f) => Value::Function(f.clone()),
+            Value::Error(ref e) => Value::Error(e.clone()),
+        };
+
+        Ok(val)
+    }
+
+    pub fn into_owned(self) -> Result<Value<'static>> {
+        let val = match self {
+            Value::Nil => Value::Nil,
+            Value::Void => Value::Void,
+            Value::Tuple(v) => Value::Tuple(into_owned_all(v)?),
+            Value::Bool(b) => Value::Bool(b),
+            Value::Int8(i) => Value::Int8(i),
+            Value::Int16(i) => Value::Int16(i),
+            Value::Int32(i) => Value::Int32(i),
+            Value::Int64(i) => Value::Int64(i),
+            Value::Uint8(i) => Value::Uint8(i),
+            Value::Uint16(i) => Value::Uint16(i),
+            Value::Uint32(i) => Value::Uint32(i),
+            Value::Uint64(i) => Value::Uint64(i),
+            Value::Float32(f) => Value::Float32(f),
+            Value::Float64(f) => Value::Float64(f),
+            Value::Custom(c) => Value::Custom(c),
+            Value::CustomSlot(s) => Value::Custom(s.take().ok_or(Error::ValueMoved)?),
+            Value::Shared(s) => Value::Shared(s),
+            Value::Array(v) => Value::Array(into_owned_all(v)?),
+            Value::Map(m) => Value::Map(m.into_iter()
+                .map(|(k, v)| Ok((k.into_owned()?, v.into_owned()?)))
+                .collect::<Result<_>>()?),
+            Value::String(s) => Value::String(s),
+            Value::Function(f) => Value::Function(f),
+            Value::Error(e) => Value::Error(e),
+            other @ Value::CustomRef(_) | other @ Value::CustomMut(_) => {
+                return Err(Error::WrongType {
+                    expected: ValueTy::Custom,
+                    found: other.ty(),
+                })
+            }
+        };
+
+        Ok(val)
+    }
+
+    pub fn to_key(&self) -> Result<HashKey> {
+        HashKey::new(self)
+    }
+}
+
+fn try_clone_all<'a>(v: &[Value<'a>], map: &TyMapMut) -> Result<Vec<Value<'a>>> {
+    v.iter().map(|elem| elem.try_clone(map)).collect()
+}
+
+fn into_owned_all(v: Vec<Value>) -> Result<Vec<Value<'static>>> {
+    v.into_iter().map(Value::into_owned).collect()
 }
 
 impl<'a> fmt::Debug for Value<'a> {
@@ -134,8 +592,12 @@ impl<'a> fmt::Debug for Value<'a> {
             Value::Custom(ref c) => f.debug_tuple("Custom").field(&c.type_id()).finish(),
             Value::CustomRef(_) => f.debug_tuple("CustomRef").finish(),
             Value::CustomMut(_) => f.debug_tuple("CustomMut").finish(),
+            Value::CustomSlot(_) => f.debug_tuple("CustomSlot").finish(),
+            Value::Shared(ref s) => f.debug_tuple("Shared").field(&s.inner_type_name()).finish(),
             Value::Array(ref c) => f.debug_tuple("Array").field(c).finish(),
+            Value::Map(ref c) => f.debug_tuple("Map").field(c).finish(),
             Value::String(ref c) => f.debug_tuple("String").field(c).finish(),
+            Value::Function(ref func) => f.debug_tuple("Function").field(&func.ident).finish(),
             Value::Error(ref e) => f.debug_tuple("Error").field(e).finish(),
         }
     }
@@ -156,7 +618,7 @@ impl<'a> Into<Result<Value<'a>>> for Value<'a> {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ValueTy {
     Unknown,
     Void,
@@ -175,15 +637,19 @@ pub enum ValueTy {
     Custom,
     CustomRef,
     CustomMut,
+    Shared,
     Option(Box<ValueTy>),
     Array(Box<ValueTy>),
+    Map(Box<ValueTy>, Box<ValueTy>),
     String,
+    Function(Vec<ValueTy>, Box<ValueTy>),
+    Rest(Box<ValueTy>),
 }
 
 impl<'a, 'b> From<&'a Value<'b>> for ValueTy {
     fn from(val: &Value) -> Self {
         match *val {
-            Value::Nil => ValueTy::Unknown,
+            Value::Nil => ValueTy::Option(Box::new(ValueTy::Unknown)),
             Value::Void => ValueTy::Void,
             Value::Tuple(ref v) => ValueTy::Tuple(v.iter().map(From::from).collect()),
             Value::Bool(_) => ValueTy::Bool,
@@ -200,8 +666,21 @@ impl<'a, 'b> From<&'a Value<'b>> for ValueTy {
             Value::Custom(_) => ValueTy::Custom,
             Value::CustomRef(_) => ValueTy::CustomRef,
             Value::CustomMut(_) => ValueTy::CustomMut,
-            Value::Array(ref a) => a.iter().next().map(From::from).unwrap_or(ValueTy::Unknown),
+            Value::CustomSlot(_) => ValueTy::Custom,
+            Value::Shared(_) => ValueTy::Shared,
+            Value::Array(ref a) => ValueTy::Array(Box::new(
+                a.iter().next().map(From::from).unwrap_or(ValueTy::Unknown),
+            )),
+            Value::Map(ref m) => {
+                let (k, v) = m.iter()
+                    .next()
+                    .map(|&(ref k, ref v)| (From::from(k), From::from(v)))
+                    .unwrap_or((ValueTy::Unknown, ValueTy::Unknown));
+
+                ValueTy::Map(Box::new(k), Box::new(v))
+            }
             Value::String(_) => ValueTy::String,
+            Value::Function(ref f) => ValueTy::Function(f.args.clone(), Box::new(f.ret.clone())),
             Value::Error(_) => ValueTy::Unknown,
         }
     }
@@ -0,0 +1,249 @@
+use {Error, Function, Module, Result, Str, Ty, ValueTy};
+
+pub fn resolve<'f, I>(ident: &str, candidates: I, found: &[ValueTy]) -> Result<&'f Function>
+where
+    I: IntoIterator<Item = &'f Function>,
+{
+    let candidates: Vec<&Function> = candidates.into_iter().collect();
+    let mut best: Vec<&Function> = vec![];
+    let mut best_score = 0;
+
+    for &func in &candidates {
+        let score = match score_all(func, found) {
+            Some(score) => score,
+            None => continue,
+        };
+
+        if best.is_empty() || score > best_score {
+            best = vec![func];
+            best_score = score;
+        } else if score == best_score {
+            best.push(func);
+        }
+    }
+
+    match best.len() {
+        0 => Err(Error::NoOverload {
+            ident: Str::from(ident.to_owned()),
+            found: found.to_vec(),
+            candidates: candidates.iter().map(|f| f.args.clone()).collect(),
+        }),
+        1 => Ok(best[0]),
+        _ => Err(Error::AmbiguousOverload {
+            ident: Str::from(ident.to_owned()),
+            signatures: best.iter().map(|f| f.args.clone()).collect(),
+        }),
+    }
+}
+
+pub fn check(ty: &Ty) -> Result<()> {
+    let operators: Vec<Function> = ty.operators.iter().map(|&(_, ref f)| f.clone()).collect();
+
+    check_all(&ty.ident, &[&ty.functions, &ty.methods, &operators])
+}
+
+pub fn check_module(module: &Module) -> Result<()> {
+    check_all(&module.ident, &[&module.functions])?;
+    for nested in &module.modules {
+        check_module(nested)?;
+    }
+
+    Ok(())
+}
+
+fn check_all(ident: &str, groups: &[&Vec<Function>]) -> Result<()> {
+    for funcs in groups {
+        for (i, a) in funcs.iter().enumerate() {
+            let conflict = funcs[i + 1..]
+                .iter()
+                .find(|b| a.ident == b.ident && !distinct(a, b));
+
+            if let Some(b) = conflict {
+                return Err(Error::AmbiguousOverload {
+                    ident: Str::from(format!("{}.{}", ident, a.ident)),
+                    signatures: vec![a.args.clone(), b.args.clone()],
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn signature(args: &[ValueTy]) -> String {
+    let args: Vec<String> = args.iter().map(|ty| format!("{:?}", ty)).collect();
+
+    format!("({})", args.join(", "))
+}
+
+fn score_all(func: &Function, found: &[ValueTy]) -> Option<u32> {
+    if found.len() < func.min_args() || found.len() > max_args(func) {
+        return None;
+    }
+
+    found
+        .iter()
+        .enumerate()
+        .map(|(i, f)| score(param(func, i), f))
+        .fold(Some(0), |acc, s| Some(acc? + s?))
+}
+
+fn score(expected: &ValueTy, found: &ValueTy) -> Option<u32> {
+    match (expected, found) {
+        (&ValueTy::Unknown, _) | (_, &ValueTy::Unknown) => Some(1),
+        (&ValueTy::Option(_), &ValueTy::Option(_)) => Some(2),
+        (&ValueTy::Option(ref inner), found) => score(inner, found),
+        (expected, found) => {
+            let (e, f) = (Class::of(expected), Class::of(found));
+            if e == f {
+                Some(2)
+            } else if e.is_number() && f.is_number() || e.is_table() && f.is_table() {
+                Some(1)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn distinct(a: &Function, b: &Function) -> bool {
+    let min = a.min_args().max(b.min_args());
+    let max = max_args(a)
+        .min(max_args(b))
+        .min(a.args.len().max(b.args.len()).max(min));
+
+    (min..max + 1).all(|n| {
+        (0..n).any(|i| {
+            let (a, b) = (Class::of(param(a, i)), Class::of(param(b, i)));
+
+            a != Class::Unknown && b != Class::Unknown && a != b
+        })
+    })
+}
+
+fn max_args(func: &Function) -> usize {
+    if func.is_variadic() {
+        usize::max_value()
+    } else {
+        func.args.len()
+    }
+}
+
+fn param(func: &Function, i: usize) -> &ValueTy {
+    match func.args[i.min(func.args.len() - 1)] {
+        ValueTy::Rest(ref inner) if i + 1 >= func.args.len() => inner,
+        ref ty => ty,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Class {
+    Unknown,
+    Void,
+    Bool,
+    Int,
+    Float,
+    Custom,
+    Array,
+    Map,
+    String,
+    Function,
+}
+
+impl Class {
+    fn of(ty: &ValueTy) -> Self {
+        match *ty {
+            ValueTy::Unknown => Class::Unknown,
+            ValueTy::Void => Class::Void,
+            ValueTy::Bool => Class::Bool,
+            ValueTy::Int8
+            | ValueTy::Int16
+            | ValueTy::Int32
+            | ValueTy::Int64
+            | ValueTy::Uint8
+            | ValueTy::Uint16
+            | ValueTy::Uint32
+            | ValueTy::Uint64 => Class::Int,
+            ValueTy::Float32 | ValueTy::Float64 => Class::Float,
+            ValueTy::Custom | ValueTy::CustomRef | ValueTy::CustomMut | ValueTy::Shared => {
+                Class::Custom
+            }
+            ValueTy::Option(ref inner) | ValueTy::Rest(ref inner) => Class::of(inner),
+            ValueTy::Tuple(_) | ValueTy::Array(_) => Class::Array,
+            ValueTy::Map(..) => Class::Map,
+            ValueTy::String => Class::String,
+            ValueTy::Function(..) => Class::Function,
+        }
+    }
+
+    fn is_number(self) -> bool {
+        self == Class::Int || self == Class::Float
+    }
+
+    fn is_table(self) -> bool {
+        self == Class::Array || self == Class::Map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Builder, TyBuilder};
+
+    struct Vec3(f32, f32, f32);
+
+    fn vec3() -> TyBuilder<Vec3> {
+        TyBuilder::new("Vec3")
+            .with_function("new", |()| Box::new(Vec3(0.0, 0.0, 0.0)))
+            .with_function("new", |(x, y, z): (f32, f32, f32)| {
+                Box::new(Vec3(x, y, z))
+            })
+            .with_function("new", |(o,): (Box<Vec3>,)| Box::new(Vec3(o.0, o.1, o.2)))
+    }
+
+    #[test]
+    fn by_arity_and_type() {
+        let map = Builder::default().with_ty(vec3()).finish().unwrap();
+        let ty = map.values().next().unwrap();
+        let new = |found: &[ValueTy]| resolve("Vec3.new", &ty.functions, found);
+
+        assert_eq!(new(&[]).unwrap().args, vec![]);
+        assert_eq!(
+            new(&[ValueTy::Int64, ValueTy::Float64, ValueTy::Int64])
+                .unwrap()
+                .args
+                .len(),
+            3
+        );
+        assert_eq!(new(&[ValueTy::Custom]).unwrap().args, vec![ValueTy::Custom]);
+
+        match new(&[ValueTy::String]) {
+            Err(ref e @ Error::NoOverload { .. }) => assert_eq!(
+                e.to_string(),
+                "Vec3.new: no overload matches (String); candidates: (), \
+                 (Float32, Float32, Float32), (Custom)"
+            ),
+            other => panic!("Expected no overload, got {:?}", other.map(|f| &f.args)),
+        }
+    }
+
+    #[test]
+    fn ambiguous_registration() {
+        let res = Builder::default()
+            .with_ty(vec3().with_function("new", |(x, y, z): (f64, f64, f64)| {
+                Box::new(Vec3(x as f32, y as f32, z as f32))
+            }))
+            .finish();
+
+        match res {
+            Err(Error::AmbiguousOverload { ref ident, .. }) if ident == "Vec3.new" => {}
+            Err(e) => panic!("Expected ambiguity, got {:?}", e),
+            Ok(_) => panic!("Expected ambiguity"),
+        }
+
+        let res = Builder::default()
+            .with_ty(vec3().with_function("new", |(x,): (f32,)| Box::new(Vec3(x, x, x))))
+            .finish();
+        assert!(res.is_ok());
+    }
+}
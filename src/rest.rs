@@ -0,0 +1,107 @@
+use std::ops::{Deref, DerefMut};
+
+use conv::FromValue;
+use {Error, Result, Value, ValueTy};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rest<T>(pub Vec<T>);
+
+impl<T> Rest<T> {
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for Rest<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Rest<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+impl<'a, T> FromValue<'a> for Rest<T>
+where
+    T: FromValue<'a>,
+{
+    fn out_ty() -> ValueTy {
+        ValueTy::Rest(Box::new(T::out_ty()))
+    }
+
+    fn from(v: Value<'a>) -> Result<Self> {
+        match v.into_res()? {
+            Value::Tuple(v) => v
+                .into_iter()
+                .enumerate()
+                .map(|(index, v)| {
+                    T::from(v).map_err(|e| Error::Argument {
+                        index,
+                        name: None,
+                        error: Box::new(e),
+                    })
+                })
+                .collect::<Result<_>>()
+                .map(Rest),
+            other => Err(Error::WrongType {
+                expected: Self::out_ty(),
+                found: other.ty(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Builder, Str, TyBuilder};
+
+    struct Math;
+
+    #[test]
+    fn variadic_max() {
+        let map = Builder::default()
+            .with_ty(
+                TyBuilder::<Math>::new("Math")
+                    .with_function("max", |(a, b, rest): (i32, i32, Rest<i32>)| {
+                        rest.iter().fold(a.max(b), |m, &x| m.max(x))
+                    }),
+            )
+            .finish()
+            .unwrap();
+        let max = &map.values().next().unwrap().functions[0];
+
+        assert!(max.is_variadic());
+        assert_eq!(max.min_args(), 2);
+        assert_eq!((max.exec)(vec![Value::Int32(1), Value::Int32(2)]), Value::Int32(2));
+        assert_eq!(
+            (max.exec)((1..6).map(Value::Int32).collect()),
+            Value::Int32(5)
+        );
+
+        let args = vec![
+            Value::Int32(1),
+            Value::Int32(2),
+            Value::Int32(3),
+            Value::String(Str::from("4")),
+        ];
+        match (max.exec)(args) {
+            Value::Error(ref e) => assert_eq!(
+                e.to_string(),
+                "Math.max: argument 3: expected Int32, found String"
+            ),
+            other => panic!("Expected error, got {:?}", other),
+        }
+        match (max.exec)(vec![Value::Int32(1)]) {
+            Value::Error(ref e) => {
+                assert_eq!(e.to_string(), "Math.max: expected 2 arguments, found 1")
+            }
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+}
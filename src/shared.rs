@@ -0,0 +1,248 @@
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, LockResult, Mutex, RwLock, TryLockError, TryLockResult};
+
+use {type_name_of, Any, Error, Result};
+
+pub trait Shared: Any {
+    fn as_any(&self) -> &Any;
+
+    fn inner_type_id(&self) -> TypeId;
+
+    fn inner_type_name(&self) -> &'static str;
+
+    fn visit_ref(&self, f: &mut FnMut(&Any)) -> Result<()>;
+
+    fn visit_mut(&self, f: &mut FnMut(&mut Any)) -> Result<()>;
+
+    fn share(&self) -> Box<Shared>;
+}
+
+impl Shared {
+    pub fn with_ref<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&Any) -> R,
+    {
+        let mut f = Some(f);
+        let mut res = None;
+        self.visit_ref(&mut |any| res = f.take().map(|f| f(any)))?;
+
+        Ok(res.expect("visitor was not called"))
+    }
+
+    pub fn with_mut<F, R>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut Any) -> R,
+    {
+        let mut f = Some(f);
+        let mut res = None;
+        self.visit_mut(&mut |any| res = f.take().map(|f| f(any)))?;
+
+        Ok(res.expect("visitor was not called"))
+    }
+}
+
+impl<T> Shared for Rc<RefCell<T>>
+where
+    T: Any,
+{
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn inner_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn inner_type_name(&self) -> &'static str {
+        type_name_of::<T>()
+    }
+
+    fn visit_ref(&self, f: &mut FnMut(&Any)) -> Result<()> {
+        let borrow = self.try_borrow().map_err(|_| already_borrowed::<T>())?;
+        f(&*borrow);
+
+        Ok(())
+    }
+
+    fn visit_mut(&self, f: &mut FnMut(&mut Any)) -> Result<()> {
+        let mut borrow = self.try_borrow_mut().map_err(|_| already_borrowed::<T>())?;
+        f(&mut *borrow);
+
+        Ok(())
+    }
+
+    fn share(&self) -> Box<Shared> {
+        Box::new(self.clone())
+    }
+}
+
+// A read-only `Arc<T>` impl would overlap with the lock impls below.
+impl<T> Shared for Arc<RwLock<T>>
+where
+    T: Any,
+{
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn inner_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn inner_type_name(&self) -> &'static str {
+        type_name_of::<T>()
+    }
+
+    fn visit_ref(&self, f: &mut FnMut(&Any)) -> Result<()> {
+        lock::<T, _, _, _>(&**self, RwLock::try_read, RwLock::read, |guard| f(&*guard))
+    }
+
+    fn visit_mut(&self, f: &mut FnMut(&mut Any)) -> Result<()> {
+        lock::<T, _, _, _>(&**self, RwLock::try_write, RwLock::write, |mut guard| {
+            f(&mut *guard)
+        })
+    }
+
+    fn share(&self) -> Box<Shared> {
+        Box::new(self.clone())
+    }
+}
+
+impl<T> Shared for Arc<Mutex<T>>
+where
+    T: Any,
+{
+    fn as_any(&self) -> &Any {
+        self
+    }
+
+    fn inner_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn inner_type_name(&self) -> &'static str {
+        type_name_of::<T>()
+    }
+
+    fn visit_ref(&self, f: &mut FnMut(&Any)) -> Result<()> {
+        lock::<T, _, _, _>(&**self, Mutex::try_lock, Mutex::lock, |guard| f(&*guard))
+    }
+
+    fn visit_mut(&self, f: &mut FnMut(&mut Any)) -> Result<()> {
+        lock::<T, _, _, _>(&**self, Mutex::try_lock, Mutex::lock, |mut guard| {
+            f(&mut *guard)
+        })
+    }
+
+    fn share(&self) -> Box<Shared> {
+        Box::new(self.clone())
+    }
+}
+
+fn already_borrowed<T>() -> Error {
+    Error::AlreadyBorrowed {
+        ty: type_name_of::<T>(),
+    }
+}
+
+thread_local! {
+    static HELD: RefCell<Vec<usize>> = RefCell::new(Vec::new());
+}
+
+struct Held(usize);
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(pos) = held.iter().rposition(|&addr| addr == self.0) {
+                held.remove(pos);
+            }
+        });
+    }
+}
+
+// Other threads are waited for; only a lock already held by this thread is
+// tried without blocking, so re-entrant borrows fail instead of deadlocking.
+fn lock<'l, T, L, G, F>(
+    lock: &'l L,
+    try_lock: fn(&'l L) -> TryLockResult<G>,
+    block: fn(&'l L) -> LockResult<G>,
+    f: F,
+) -> Result<()>
+where
+    F: FnOnce(G),
+{
+    let addr = lock as *const L as usize;
+    let reentrant = HELD.with(|held| held.borrow().contains(&addr));
+    let guard = if reentrant {
+        match try_lock(lock) {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(already_borrowed::<T>()),
+        }
+    } else {
+        block(lock).unwrap_or_else(|poisoned| poisoned.into_inner())
+    };
+
+    HELD.with(|held| held.borrow_mut().push(addr));
+    let _held = Held(addr);
+    f(guard);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reentrant(shared: Box<Shared>) {
+        let other = shared.share();
+        let res = shared.with_ref(|_| other.with_mut(|_| ())).unwrap();
+        match res {
+            Err(Error::AlreadyBorrowed { ty }) => assert!(ty.ends_with("i32")),
+            other => panic!("Unexpected {:?}", other),
+        }
+
+        shared.with_mut(|_| ()).unwrap();
+    }
+
+    #[test]
+    fn reentrant_borrow() {
+        reentrant(Box::new(Rc::new(RefCell::new(1))));
+        reentrant(Box::new(Arc::new(RwLock::new(1))));
+        reentrant(Box::new(Arc::new(Mutex::new(1))));
+    }
+
+    fn contended<S>(shared: S)
+    where
+        S: Shared + Clone + Send + 'static,
+    {
+        use std::sync::mpsc::channel;
+        use std::thread;
+        use std::time::Duration;
+
+        let (tx, rx) = channel();
+        let other = shared.clone();
+        let handle = thread::spawn(move || {
+            (&other as &Shared)
+                .with_mut(|_| {
+                    tx.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(50));
+                })
+                .unwrap();
+        });
+
+        rx.recv().unwrap();
+        (&shared as &Shared).with_mut(|_| ()).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn cross_thread_borrow() {
+        contended(Arc::new(RwLock::new(1)));
+        contended(Arc::new(Mutex::new(1)));
+    }
+}
@@ -0,0 +1,76 @@
+use std::ops::Deref;
+
+use TyMap;
+
+pub enum Local {}
+
+pub enum ThreadSafe {}
+
+pub trait Captures<M> {}
+
+impl<T: ?Sized> Captures<Local> for T {}
+
+impl<T: ?Sized + Send + Sync> Captures<ThreadSafe> for T {}
+
+#[derive(Clone)]
+pub struct SyncTyMap {
+    map: TyMap,
+}
+
+impl SyncTyMap {
+    pub(crate) unsafe fn new(map: TyMap) -> Self {
+        SyncTyMap { map }
+    }
+}
+
+impl Deref for SyncTyMap {
+    type Target = TyMap;
+
+    fn deref(&self) -> &TyMap {
+        &self.map
+    }
+}
+
+// Only `Builder<ThreadSafe>` creates this, which requires every stored closure to be
+// `Send + Sync`; mutable access to the map and types is limited to `Local` builders.
+unsafe impl Send for SyncTyMap {}
+unsafe impl Sync for SyncTyMap {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use {Builder, ThreadSafe, TyBuilder, Value};
+
+    struct Foo(i32);
+
+    #[test]
+    fn share_between_threads() {
+        let offset = Arc::new(2);
+        let map = Builder::default()
+            .with_ty(
+                TyBuilder::<Foo, ThreadSafe>::new("Foo")
+                    .with_function("add", move |(x,): (i32,)| x + *offset),
+            )
+            .finish_sync()
+            .unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    let ty = map.values().next().unwrap();
+                    match (ty.functions[0].exec)(vec![Value::Int32(i)]) {
+                        Value::Int32(x) => x,
+                        other => panic!("Expected Int32, got {:?}", other),
+                    }
+                })
+            })
+            .collect();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), i as i32 + 2);
+        }
+    }
+}